@@ -0,0 +1,9 @@
+ALTER TABLE mn_entry ADD COLUMN kind VARCHAR(32) NOT NULL DEFAULT 'counter';
+ALTER TABLE mn_entry ADD COLUMN major BIGINT; -- nullable, semver series only
+ALTER TABLE mn_entry ADD COLUMN minor BIGINT; -- nullable, semver series only
+ALTER TABLE mn_entry ADD COLUMN patch BIGINT; -- nullable, semver series only
+
+ALTER TABLE mn_entry_version ADD COLUMN kind VARCHAR(32) NOT NULL DEFAULT 'counter';
+ALTER TABLE mn_entry_version ADD COLUMN major BIGINT; -- nullable, semver series only
+ALTER TABLE mn_entry_version ADD COLUMN minor BIGINT; -- nullable, semver series only
+ALTER TABLE mn_entry_version ADD COLUMN patch BIGINT; -- nullable, semver series only
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS mn_entry_channel (
+  key        VARCHAR(256) NOT NULL,
+  creator_id BIGINT NOT NULL REFERENCES mn_account (id),
+  channel    VARCHAR(64) NOT NULL,
+  token      VARCHAR(256), -- nullable
+  base       BIGINT NOT NULL, -- the parent series value the channel counter is tied to
+  value      BIGINT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  PRIMARY KEY (key, creator_id, channel)
+);
+
+ALTER TABLE mn_entry_version ADD COLUMN channel VARCHAR(64); -- nullable, pre-releases only
+ALTER TABLE mn_entry_version ADD COLUMN channel_value BIGINT; -- nullable, pre-releases only
+ALTER TABLE mn_entry_version ADD COLUMN build VARCHAR(256); -- nullable
@@ -0,0 +1,2 @@
+ALTER TABLE mn_entry ADD COLUMN step BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE mn_entry ADD COLUMN max BIGINT; -- nullable, unbounded by default
@@ -0,0 +1 @@
+ALTER TABLE mn_entry ADD COLUMN description VARCHAR(1024); -- nullable
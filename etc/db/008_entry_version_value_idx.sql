@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS mn_entry_version_value_idx ON mn_entry_version (key, creator_id, value);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS mn_entry_audit (
+  id               BIGSERIAL PRIMARY KEY,
+  key              VARCHAR(256) NOT NULL,
+  creator_id       BIGINT NOT NULL REFERENCES mn_account (id),
+  actor            VARCHAR(256) NOT NULL, -- the API key that made the change
+  previous_value   BIGINT NOT NULL,
+  value            BIGINT NOT NULL,
+  previous_version VARCHAR(64), -- nullable, semver series only
+  version          VARCHAR(64), -- nullable, semver series only
+  forced           BOOLEAN NOT NULL DEFAULT false,
+  reason           VARCHAR(1024), -- nullable
+  created_at       TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS mn_entry_audit_key_idx ON mn_entry_audit (key, creator_id, created_at);
@@ -0,0 +1,3 @@
+ALTER TABLE mn_entry ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE; -- nullable, set when archived
+
+CREATE INDEX IF NOT EXISTS mn_entry_deleted_at_idx ON mn_entry (deleted_at) WHERE deleted_at IS NOT NULL;
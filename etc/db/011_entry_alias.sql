@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS mn_entry_alias (
+  alias      VARCHAR(256) NOT NULL,
+  creator_id BIGINT NOT NULL REFERENCES mn_account (id),
+  key        VARCHAR(256) NOT NULL, -- the series the alias refers to
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  PRIMARY KEY (alias, creator_id)
+);
+
+CREATE INDEX IF NOT EXISTS mn_entry_alias_key_idx ON mn_entry_alias (key, creator_id);
@@ -0,0 +1,7 @@
+ALTER TABLE mn_entry ADD COLUMN IF NOT EXISTS period VARCHAR(16); -- nullable, calver series only
+ALTER TABLE mn_entry ADD COLUMN IF NOT EXISTS utc_offset INTEGER; -- nullable, minutes
+ALTER TABLE mn_entry ADD COLUMN IF NOT EXISTS period_start DATE; -- nullable
+ALTER TABLE mn_entry ADD COLUMN IF NOT EXISTS period_value BIGINT; -- nullable
+
+ALTER TABLE mn_entry_version ADD COLUMN IF NOT EXISTS period_start DATE; -- nullable
+ALTER TABLE mn_entry_version ADD COLUMN IF NOT EXISTS period_value BIGINT; -- nullable
@@ -0,0 +1 @@
+ALTER TABLE mn_entry ADD COLUMN IF NOT EXISTS template VARCHAR(256); -- nullable
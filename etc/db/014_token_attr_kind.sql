@@ -0,0 +1 @@
+ALTER TABLE mn_token_attr ADD COLUMN IF NOT EXISTS kind VARCHAR(16) NOT NULL DEFAULT 'string'; -- the JSON type of the value
@@ -0,0 +1 @@
+ALTER TABLE mn_entry ADD COLUMN IF NOT EXISTS attr_schema TEXT; -- nullable, JSON
@@ -0,0 +1,4 @@
+ALTER TABLE mn_account ADD COLUMN IF NOT EXISTS attr_max_value_bytes BIGINT; -- overrides the deployment limit when set
+ALTER TABLE mn_account ADD COLUMN IF NOT EXISTS attr_max_per_token BIGINT;
+ALTER TABLE mn_account ADD COLUMN IF NOT EXISTS attr_max_account_bytes BIGINT;
+CREATE INDEX IF NOT EXISTS mn_token_attr_creator_id_idx ON mn_token_attr (creator_id);
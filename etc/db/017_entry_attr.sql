@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS mn_entry_attr (
+  key         VARCHAR(256) NOT NULL,
+  creator_id  BIGINT NOT NULL,
+  name        VARCHAR(256) NOT NULL,
+  kind        VARCHAR(16) NOT NULL DEFAULT 'string', -- the JSON type of the value
+  value       TEXT NOT NULL,
+  created_at  TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  updated_at  TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  PRIMARY KEY (key, creator_id, name)
+);
+
+CREATE INDEX IF NOT EXISTS mn_entry_attr_creator_id_idx ON mn_entry_attr (creator_id);
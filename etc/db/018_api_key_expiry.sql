@@ -0,0 +1,3 @@
+ALTER TABLE mn_api_key ADD COLUMN IF NOT EXISTS not_before TIMESTAMP WITH TIME ZONE; -- the key is valid immediately if unset
+ALTER TABLE mn_api_key ADD COLUMN IF NOT EXISTS expires_at TIMESTAMP WITH TIME ZONE; -- the key never expires if unset
+CREATE INDEX IF NOT EXISTS mn_api_key_expires_at_idx ON mn_api_key (expires_at);
@@ -0,0 +1,12 @@
+-- hash plaintext secrets with bcrypt, which is what the service uses to verify
+-- them. this requires the pgcrypto extension, which the service does not
+-- install since that usually requires more privileges than it has; if it is
+-- not installed, the remaining plaintext secrets are hashed by the service when
+-- it starts
+DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pgcrypto') THEN
+    UPDATE mn_api_key SET secret = crypt(secret, gen_salt('bf', 10)) WHERE secret NOT LIKE '$2_$%';
+  END IF;
+END
+$$;
@@ -0,0 +1 @@
+ALTER TABLE mn_entry ADD COLUMN IF NOT EXISTS timezone VARCHAR(64); -- nullable, an IANA timezone name, calver series only
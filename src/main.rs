@@ -9,13 +9,17 @@ use std::collections;
 
 use bytes;
 use chrono;
-use warp::{http, Filter};
+use warp::{http, Filter, Reply};
 use envconfig::Envconfig;
-use serde_json::json;
+use serde_json::{self, json};
 
 use crate::model::apikey::{self, Authenticate, AccessControl};
 
 const HEADER_AUTHORIZATION: &str = "Authorization";
+const HEADER_ACCEPT: &str = "Accept";
+const HEADER_IF_MATCH: &str = "If-Match";
+const PURGE_INTERVAL_SECS: u64 = 60 * 60;
+const MAX_CONFIG_DAYS: i64 = 100 * 365;
 
 #[derive(Envconfig)]
 pub struct Config {
@@ -27,6 +31,81 @@ pub struct Config {
   pub root_api_key: Option<String>,
   #[envconfig(from = "ROOT_API_SECRET")]
   pub root_api_secret: Option<String>,
+  #[envconfig(from = "ARCHIVE_RETENTION_DAYS")]
+  pub archive_retention_days: Option<i64>, // archived series are never purged if unset
+  #[envconfig(from = "ATTR_MAX_VALUE_BYTES")]
+  pub attr_max_value_bytes: Option<i64>,
+  #[envconfig(from = "ATTR_MAX_PER_TOKEN")]
+  pub attr_max_per_token: Option<i64>,
+  #[envconfig(from = "ATTR_MAX_ACCOUNT_BYTES")]
+  pub attr_max_account_bytes: Option<i64>,
+  #[envconfig(from = "MAX_BODY_BYTES")]
+  pub max_body_bytes: Option<u64>, // the largest request body accepted when storing attributes
+  #[envconfig(from = "MAX_KEY_LIFETIME_DAYS")]
+  pub max_key_lifetime_days: Option<i64>, // api keys may be created without an expiry if unset
+  #[envconfig(from = "EXPIRED_KEY_RETENTION_DAYS")]
+  pub expired_key_retention_days: Option<i64>, // expired api keys are never purged if unset
+}
+
+impl Config {
+  // The attribute limits of the deployment, which apply to every account
+  // that does not override them. An account must be able to store at least
+  // one value of the largest size.
+  fn limits(&self) -> Result<model::limits::Limits, String> {
+    let limits = model::limits::Limits::default().with_overrides(&model::limits::Overrides{
+      max_value_bytes: config_limit("ATTR_MAX_VALUE_BYTES", self.attr_max_value_bytes)?,
+      max_attrs_per_token: config_limit("ATTR_MAX_PER_TOKEN", self.attr_max_per_token)?,
+      max_account_bytes: config_limit("ATTR_MAX_ACCOUNT_BYTES", self.attr_max_account_bytes)?,
+    });
+    if limits.max_account_bytes < limits.max_value_bytes {
+      return Err(format!("ATTR_MAX_ACCOUNT_BYTES must be at least ATTR_MAX_VALUE_BYTES: {} < {}", limits.max_account_bytes, limits.max_value_bytes));
+    }
+    Ok(limits)
+  }
+  
+  // The largest request body accepted when storing attributes, which must be
+  // able to carry a value of the largest size.
+  fn max_body_bytes(&self, limits: &model::limits::Limits) -> Result<u64, String> {
+    let max = self.max_body_bytes.unwrap_or(model::limits::DEFAULT_MAX_BODY_BYTES);
+    if (max as i64) < limits.max_value_bytes {
+      return Err(format!("MAX_BODY_BYTES must be at least ATTR_MAX_VALUE_BYTES: {} < {}", max, limits.max_value_bytes));
+    }
+    Ok(max)
+  }
+  
+  // The period archived series are retained for, if they are purged at all.
+  fn archive_retention(&self) -> Result<Option<chrono::Duration>, String> {
+    config_days("ARCHIVE_RETENTION_DAYS", self.archive_retention_days, 0)
+  }
+  
+  // The period expired API keys are retained for, if they are purged at all.
+  fn expired_key_retention(&self) -> Result<Option<chrono::Duration>, String> {
+    config_days("EXPIRED_KEY_RETENTION_DAYS", self.expired_key_retention_days, 0)
+  }
+  
+  // The longest period an API key may be valid for, if it is limited.
+  fn max_key_lifetime(&self) -> Result<Option<chrono::Duration>, String> {
+    config_days("MAX_KEY_LIFETIME_DAYS", self.max_key_lifetime_days, 1)
+  }
+}
+
+// Convert a number of days from the configuration to a duration, rejecting
+// values which are below the minimum or too large to be used as an offset
+// from the current time.
+fn config_days(name: &str, days: Option<i64>, min: i64) -> Result<Option<chrono::Duration>, String> {
+  match days {
+    Some(days) if days < min || days > MAX_CONFIG_DAYS => Err(format!("{} must be between {} and {}: {}", name, min, MAX_CONFIG_DAYS, days)),
+    Some(days) => Ok(Some(chrono::Duration::days(days))),
+    None => Ok(None),
+  }
+}
+
+// Check a limit from the configuration, which must be positive if it is set.
+fn config_limit(name: &str, limit: Option<i64>) -> Result<Option<i64>, String> {
+  match limit {
+    Some(limit) if limit < 1 => Err(format!("{} must be positive: {}", name, limit)),
+    limit => Ok(limit),
+  }
 }
 
 fn root_authorization(key: String, secret: String) -> apikey::Authorization {
@@ -41,8 +120,10 @@ fn root_authorization(key: String, secret: String) -> apikey::Authorization {
     api_key: apikey::ApiKey{
       id: 0,
       key: key,
-      secret: secret,
+      secret: Some(secret),
     },
+    not_before: None,
+    expires_at: None,
   }
 }
 
@@ -54,6 +135,26 @@ async fn main() -> Result<(), error::Error> {
     Err(err) => panic!("*** Could not load configuration from environment: {}", err),
   };
   
+  let limits = match conf.limits() {
+    Ok(limits) => limits,
+    Err(err) => panic!("*** Invalid configuration: {}", err),
+  };
+  let max_body = match conf.max_body_bytes(&limits) {
+    Ok(max) => max,
+    Err(err) => panic!("*** Invalid configuration: {}", err),
+  };
+  let archive_retention = match conf.archive_retention() {
+    Ok(retention) => retention,
+    Err(err) => panic!("*** Invalid configuration: {}", err),
+  };
+  let expired_key_retention = match conf.expired_key_retention() {
+    Ok(retention) => retention,
+    Err(err) => panic!("*** Invalid configuration: {}", err),
+  };
+  let max_key_lifetime = match conf.max_key_lifetime() {
+    Ok(lifetime) => lifetime,
+    Err(err) => panic!("*** Invalid configuration: {}", err),
+  };
   let root = match conf.root_api_key {
     Some(key) => Some(root_authorization(key, conf.root_api_secret.unwrap())),
     None => None,
@@ -65,7 +166,7 @@ async fn main() -> Result<(), error::Error> {
     println!("----> Connecting to database");
   }
   
-  let store = store::Store::new(&conf.db_dsn).await?;
+  let store = store::Store::new(&conf.db_dsn, limits).await?;
   let applied = store.migrate("./etc/db").await?;
   if applied.len() > 0 {
     println!("----> Applied migrations: {:?}", applied);
@@ -73,10 +174,25 @@ async fn main() -> Result<(), error::Error> {
     println!("----> Applied migrations: none");
   }
   
+  tokio::spawn(hash_secrets(store.clone()));
+  if let Some(retention) = archive_retention {
+    println!("----> Purging series archived for more than {} days", retention.num_days());
+    tokio::spawn(purge_archived(store.clone(), retention));
+  }
+  if let Some(retention) = expired_key_retention {
+    println!("----> Purging API keys expired for more than {} days", retention.num_days());
+    tokio::spawn(purge_expired_keys(store.clone(), retention));
+  }
+  
   let store_filter = warp::any().map(move || store.clone());
   let root_filter = warp::any().map(move || root.clone());
+  let lifetime_filter = warp::any().map(move || max_key_lifetime);
+  
+  let json_content = warp::reply::with::default_header("Content-Type", "application/json");
   
-  let json_content = warp::reply::with::header("Content-Type", "application/json");
+  let output_filter = warp::query::<model::output::Options>()
+    .and(warp::header::optional::<String>(HEADER_ACCEPT))
+    .map(|opts: model::output::Options, accept: Option<String>| opts.negotiate(accept));
   
   let auth_filter = warp::any()
     .and(store_filter.clone())
@@ -95,9 +211,23 @@ async fn main() -> Result<(), error::Error> {
     .and_then(handle_fetch_account)
     .with(&json_content);
   
+  let fetch_account_limits = warp::path!("v1" / "accounts" / i64 / "limits")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_fetch_account_limits)
+    .with(&json_content);
+  
+  let store_account_limits = warp::path!("v1" / "accounts" / i64 / "limits")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_store_account_limits)
+    .with(&json_content);
+  
   let create_authorization = warp::path!("v1" / "accounts" / i64 / "grants")
     .and(store_filter.clone())
     .and(auth_filter.clone())
+    .and(lifetime_filter)
     .and(warp::body::json())
     .and_then(handle_create_authorization)
     .with(&json_content);
@@ -120,37 +250,259 @@ async fn main() -> Result<(), error::Error> {
     .and_then(handle_delete_authorization)
     .with(&json_content);
   
+  let create_entry = warp::path!("v1" / "accounts" / i64 / "series")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_create_entry)
+    .with(&json_content);
+  
+  let inc_entries = warp::path!("v1" / "accounts" / i64 / "series" / "increments")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_inc_entries)
+    .with(&json_content);
+  
+  let list_entries = warp::path!("v1" / "accounts" / i64 / "series")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::query::<model::entry::Filter>())
+    .and_then(handle_list_entries)
+    .with(&json_content);
+  
   let fetch_entry = warp::path!("v1" / "accounts" / i64 / "series" / String)
     .and(store_filter.clone())
     .and(auth_filter.clone())
+    .and(output_filter)
     .and_then(handle_fetch_entry)
     .with(&json_content);
   
+  let list_entry_versions = warp::path!("v1" / "accounts" / i64 / "series" / String / "versions")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::query::<model::entry::VersionFilter>())
+    .and_then(handle_list_entry_versions)
+    .with(&json_content);
+  
+  let list_entry_versions_with_attrs = warp::path!("v1" / "accounts" / i64 / "series" / String / "versions" / "attrs")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::query::<model::entry::VersionFilter>())
+    .and_then(handle_list_entry_versions_with_attrs)
+    .with(&json_content);
+  
+  let query_entry_versions = warp::path!("v1" / "accounts" / i64 / "series" / String / "versions" / "query")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_query_entry_versions)
+    .with(&json_content);
+  
+  let query_versions = warp::path!("v1" / "accounts" / i64 / "versions" / "query")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_query_versions)
+    .with(&json_content);
+  
+  let fetch_entry_version_by_value = warp::path!("v1" / "accounts" / i64 / "series" / String / "values" / i64)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_fetch_entry_version_by_value)
+    .with(&json_content);
+  
   let fetch_entry_version = warp::path!("v1" / "accounts" / i64 / "series" / String / String)
     .and(store_filter.clone())
     .and(auth_filter.clone())
+    .and(output_filter)
     .and_then(handle_fetch_entry_version)
     .with(&json_content);
   
   let inc_entry = warp::path!("v1" / "accounts" / i64 / "series" / String / String)
     .and(store_filter.clone())
     .and(auth_filter.clone())
+    .and(warp::query::<model::entry::Increment>())
+    .and(output_filter)
     .and_then(handle_inc_entry)
     .with(&json_content);
   
+  let set_entry = warp::path!("v1" / "accounts" / i64 / "series" / String)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_set_entry)
+    .with(&json_content);
+  
+  let store_entry_template = warp::path!("v1" / "accounts" / i64 / "series" / String / "template")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_store_entry_template)
+    .with(&json_content);
+  
+  let store_entry_schema = warp::path!("v1" / "accounts" / i64 / "series" / String / "schema")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_store_entry_schema)
+    .with(&json_content);
+  
+  let fetch_entry_schema = warp::path!("v1" / "accounts" / i64 / "series" / String / "schema")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_fetch_entry_schema)
+    .with(&json_content);
+  
+  let delete_entry_schema = warp::path!("v1" / "accounts" / i64 / "series" / String / "schema")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_delete_entry_schema)
+    .with(&json_content);
+  
+  let store_entry_attrs = warp::path!("v1" / "accounts" / i64 / "series" / String / "attrs")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::header::optional::<String>(HEADER_IF_MATCH))
+    .and(warp::body::content_length_limit(max_body))
+    .and(warp::body::json())
+    .and_then(handle_store_entry_attrs)
+    .with(&json_content);
+  
+  let patch_entry_attrs = warp::path!("v1" / "accounts" / i64 / "series" / String / "attrs")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::header::optional::<String>(HEADER_IF_MATCH))
+    .and(warp::body::content_length_limit(max_body))
+    .and(warp::body::json())
+    .and_then(handle_patch_entry_attrs)
+    .with(&json_content);
+  
+  let fetch_entry_attrs = warp::path!("v1" / "accounts" / i64 / "series" / String / "attrs")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_fetch_entry_attrs)
+    .with(&json_content);
+  
+  let delete_entry_attrs = warp::path!("v1" / "accounts" / i64 / "series" / String / "attrs")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::header::optional::<String>(HEADER_IF_MATCH))
+    .and_then(handle_delete_entry_attrs)
+    .with(&json_content);
+  
+  let store_entry_attr = warp::path!("v1" / "accounts" / i64 / "series" / String / "attrs" / String)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::query::<model::attrs::Encoding>())
+    .and(warp::body::content_length_limit(max_body))
+    .and(warp::body::bytes())
+    .and_then(handle_store_entry_attr)
+    .with(&json_content);
+  
+  let fetch_entry_attr = warp::path!("v1" / "accounts" / i64 / "series" / String / "attrs" / String)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_fetch_entry_attr)
+    .with(&json_content);
+  
+  let delete_entry_attr = warp::path!("v1" / "accounts" / i64 / "series" / String / "attrs" / String)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_delete_entry_attr)
+    .with(&json_content);
+  
+  let fetch_entry_audit = warp::path!("v1" / "accounts" / i64 / "series" / String / "audit")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_fetch_entry_audit)
+    .with(&json_content);
+  
   let delete_entry = warp::path!("v1" / "accounts" / i64 / "series" / String)
     .and(store_filter.clone())
     .and(auth_filter.clone())
+    .and(warp::query::<model::entry::Deletion>())
     .and_then(handle_delete_entry)
     .with(&json_content);
   
+  let create_entry_branch = warp::path!("v1" / "accounts" / i64 / "series" / String / "branches")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_create_entry_branch)
+    .with(&json_content);
+  
+  let list_entry_branches = warp::path!("v1" / "accounts" / i64 / "series" / String / "branches")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::query::<model::entry::Filter>())
+    .and_then(handle_list_entry_branches)
+    .with(&json_content);
+  
+  let fetch_entry_branch = warp::path!("v1" / "accounts" / i64 / "series" / String / "branches" / String)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(output_filter)
+    .and_then(handle_fetch_entry_branch)
+    .with(&json_content);
+  
+  let inc_entry_branch = warp::path!("v1" / "accounts" / i64 / "series" / String / "branches" / String / String)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::query::<model::entry::Increment>())
+    .and(output_filter)
+    .and_then(handle_inc_entry_branch)
+    .with(&json_content);
+  
+  let store_entry_alias = warp::path!("v1" / "accounts" / i64 / "series" / String / "aliases" / String)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_store_entry_alias)
+    .with(&json_content);
+  
+  let fetch_entry_aliases = warp::path!("v1" / "accounts" / i64 / "series" / String / "aliases")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_fetch_entry_aliases)
+    .with(&json_content);
+  
+  let delete_entry_alias = warp::path!("v1" / "accounts" / i64 / "series" / String / "aliases" / String)
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_delete_entry_alias)
+    .with(&json_content);
+  
+  let move_entry = warp::path!("v1" / "accounts" / i64 / "series" / String / "move")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::body::json())
+    .and_then(handle_move_entry)
+    .with(&json_content);
+  
+  let restore_entry = warp::path!("v1" / "accounts" / i64 / "series" / String / "restore")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and_then(handle_restore_entry)
+    .with(&json_content);
+  
   let store_token_attrs = warp::path!("v1" / "accounts" / i64 / "tokens" / String / String / "attrs")
     .and(store_filter.clone())
     .and(auth_filter.clone())
+    .and(warp::header::optional::<String>(HEADER_IF_MATCH))
+    .and(warp::body::content_length_limit(max_body))
     .and(warp::body::json())
     .and_then(handle_store_token_attrs)
     .with(&json_content);
   
+  let patch_token_attrs = warp::path!("v1" / "accounts" / i64 / "tokens" / String / String / "attrs")
+    .and(store_filter.clone())
+    .and(auth_filter.clone())
+    .and(warp::header::optional::<String>(HEADER_IF_MATCH))
+    .and(warp::body::content_length_limit(max_body))
+    .and(warp::body::json())
+    .and_then(handle_patch_token_attrs)
+    .with(&json_content);
+  
   let fetch_token_attrs = warp::path!("v1" / "accounts" / i64 / "tokens" / String / String / "attrs")
     .and(store_filter.clone())
     .and(auth_filter.clone())
@@ -160,12 +512,15 @@ async fn main() -> Result<(), error::Error> {
   let delete_token_attrs = warp::path!("v1" / "accounts" / i64 / "tokens" / String / String / "attrs")
     .and(store_filter.clone())
     .and(auth_filter.clone())
+    .and(warp::header::optional::<String>(HEADER_IF_MATCH))
     .and_then(handle_delete_token_attrs)
     .with(&json_content);
   
   let store_token_attr = warp::path!("v1" / "accounts" / i64 / "tokens" / String / String / "attrs" / String)
     .and(store_filter.clone())
     .and(auth_filter.clone())
+    .and(warp::query::<model::attrs::Encoding>())
+    .and(warp::body::content_length_limit(max_body))
     .and(warp::body::bytes())
     .and_then(handle_store_token_attr)
     .with(&json_content);
@@ -185,58 +540,139 @@ async fn main() -> Result<(), error::Error> {
   let gets = warp::get().and(
     v1
       .or(fetch_account)
+      .or(fetch_account_limits)
       .or(list_authorizations)
       .or(fetch_authorization)
+      .or(list_entries)
       .or(fetch_entry)
+      .or(list_entry_versions)
+      .or(list_entry_versions_with_attrs)
+      .or(fetch_entry_audit)
+      .or(fetch_entry_schema)
+      .or(fetch_entry_attrs)
+      .or(fetch_entry_attr)
+      .or(fetch_entry_aliases)
+      .or(list_entry_branches)
+      .or(fetch_entry_branch)
+      .or(fetch_entry_version_by_value)
       .or(fetch_entry_version)
       .or(fetch_token_attr)
       .or(fetch_token_attrs)
       .recover(handle_rejection),
   );
   let puts = warp::put().and(
-    inc_entry
+    set_entry
+      .or(store_account_limits)
+      .or(store_entry_template)
+      .or(store_entry_schema)
+      .or(store_entry_attrs)
+      .or(store_entry_attr)
+      .or(inc_entry)
+      .or(store_entry_alias)
+      .or(inc_entry_branch)
       .or(store_token_attr)
       .or(store_token_attrs)
       .recover(handle_rejection),
   );
   let posts = warp::post().and(
     create_authorization
+      .or(create_entry)
+      .or(inc_entries)
+      .or(query_versions)
+      .or(query_entry_versions)
+      .or(restore_entry)
+      .or(move_entry)
+      .or(create_entry_branch)
       .recover(handle_rejection),
   );
   let dels = warp::delete().and(
     delete_authorization
       .or(delete_entry)
+      .or(delete_entry_alias)
+      .or(delete_entry_schema)
+      .or(delete_entry_attrs)
+      .or(delete_entry_attr)
       .or(delete_token_attr)
       .or(delete_token_attrs)
       .recover(handle_rejection),
   );
+  let patches = warp::patch().and(
+    patch_token_attrs
+      .or(patch_entry_attrs)
+      .recover(handle_rejection),
+  );
   
   println!("----> Running on :{}", conf.listen);
-  warp::serve(gets.or(puts).or(posts).or(dels))
+  warp::serve(gets.or(puts).or(posts).or(dels).or(patches))
     .run(([0, 0, 0, 0], conf.listen))
     .await;
   
   Ok(())
 }
 
-async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, std::convert::Infallible> {
+// Hash the API Key secrets which are still stored in plaintext. These are
+// secrets of keys created before secrets were hashed, which the database did
+// not hash when it was upgraded.
+async fn hash_secrets(store: store::Store) {
+  match store.hash_plaintext_secrets().await {
+    Ok(0) => {},
+    Ok(n) => println!("----> Hashed plaintext API key secrets: {}", n),
+    Err(err) => println!("*** Could not hash plaintext API key secrets: {}", err),
+  }
+}
+
+async fn purge_archived(store: store::Store, retention: chrono::Duration) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECS));
+  loop {
+    interval.tick().await;
+    match store.purge_entries(chrono::Utc::now() - retention).await {
+      Ok(n) => if n > 0 {
+        println!("----> Purged archived series: {}", n);
+      },
+      Err(err) => println!("*** Could not purge archived series: {}", err),
+    };
+  }
+}
+
+async fn purge_expired_keys(store: store::Store, retention: chrono::Duration) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECS));
+  loop {
+    interval.tick().await;
+    match store.purge_api_keys(chrono::Utc::now() - retention).await {
+      Ok(n) => if n > 0 {
+        println!("----> Purged expired API keys: {}", n);
+      },
+      Err(err) => println!("*** Could not purge expired API keys: {}", err),
+    };
+  }
+}
+
+async fn handle_rejection(err: warp::Rejection) -> Result<warp::reply::Response, std::convert::Infallible> {
   if debug::verbose() {
     println!("*** {:?}", &err);
   }
   if err.is_not_found() {
-    Ok(warp::reply::with_status("NOT_FOUND", http::StatusCode::NOT_FOUND))
+    Ok(warp::reply::with_status("NOT_FOUND", http::StatusCode::NOT_FOUND).into_response())
+  } else if err.find::<warp::reject::LengthRequired>().is_some() {
+    Ok(warp::reply::with_status("LENGTH_REQUIRED", http::StatusCode::LENGTH_REQUIRED).into_response())
+  } else if let Some(cause) = err.find::<warp::reject::PayloadTooLarge>() {
+    handle_payload_too_large(&cause.to_string())
   } else if let Some(cause) = err.find::<warp::reject::MissingHeader>() {
-    handle_missing_header(cause)
+    handle_missing_header(cause).map(|r| r.into_response())
   } else if let Some(cause) = err.find::<acl::scope::Error>() {
-    handle_scope_error(cause)
+    handle_scope_error(cause).map(|r| r.into_response())
   } else if let Some(cause) = err.find::<model::apikey::Error>() {
-    handle_apikey_error(cause)
+    handle_apikey_error(cause).map(|r| r.into_response())
+  } else if let Some(store::error::Error::ValidationError(violations)) = err.find::<store::error::Error>() {
+    handle_validation_error(violations)
+  } else if let Some(store::error::Error::PayloadTooLargeError(msg)) = err.find::<store::error::Error>() {
+    handle_payload_too_large(msg)
   } else if let Some(cause) = err.find::<store::error::Error>() {
-    handle_persist_error(cause)
+    handle_persist_error(cause).map(|r| r.into_response())
   } else if let Some(cause) = err.find::<error::Error>() {
-    handle_general_error(cause)
+    handle_general_error(cause).map(|r| r.into_response())
   }else{
-    Ok(warp::reply::with_status("INTERNAL_SERVER_ERROR", http::StatusCode::INTERNAL_SERVER_ERROR))
+    Ok(warp::reply::with_status("INTERNAL_SERVER_ERROR", http::StatusCode::INTERNAL_SERVER_ERROR).into_response())
   }
 }
 
@@ -257,6 +693,8 @@ fn handle_apikey_error(err: &model::apikey::Error) -> Result<warp::reply::WithSt
   match err {
     model::apikey::Error::Unauthorized(_) => Ok(warp::reply::with_status("UNAUTHORIZED", http::StatusCode::UNAUTHORIZED)),
     model::apikey::Error::Forbidden(_) => Ok(warp::reply::with_status("FORBIDDEN", http::StatusCode::FORBIDDEN)),
+    model::apikey::Error::Expired(_) => Ok(warp::reply::with_status("KEY_EXPIRED", http::StatusCode::UNAUTHORIZED)),
+    model::apikey::Error::NotYetValid(_) => Ok(warp::reply::with_status("KEY_NOT_YET_VALID", http::StatusCode::UNAUTHORIZED)),
     _ => Ok(warp::reply::with_status("ACL_ERROR", http::StatusCode::INTERNAL_SERVER_ERROR)),
   }
 }
@@ -264,10 +702,30 @@ fn handle_apikey_error(err: &model::apikey::Error) -> Result<warp::reply::WithSt
 fn handle_persist_error(err: &store::error::Error) -> Result<warp::reply::WithStatus<&'static str>, std::convert::Infallible> {
   match err {
     store::error::Error::NotFoundError => Ok(warp::reply::with_status("NOT_FOUND", http::StatusCode::NOT_FOUND)),
+    store::error::Error::InvalidOperationError(_) => Ok(warp::reply::with_status("BAD_REQUEST", http::StatusCode::BAD_REQUEST)),
+    store::error::Error::ConflictError(_) => Ok(warp::reply::with_status("CONFLICT", http::StatusCode::CONFLICT)),
+    store::error::Error::LimitExceededError(_) => Ok(warp::reply::with_status("LIMIT_EXCEEDED", http::StatusCode::UNPROCESSABLE_ENTITY)),
+    store::error::Error::PreconditionFailedError(_) => Ok(warp::reply::with_status("PRECONDITION_FAILED", http::StatusCode::PRECONDITION_FAILED)),
     _ => Ok(warp::reply::with_status("PERSISTENCE_ERROR", http::StatusCode::INTERNAL_SERVER_ERROR)),
   }
 }
 
+fn handle_validation_error(violations: &[model::schema::Violation]) -> Result<warp::reply::Response, std::convert::Infallible> {
+  let body = json!({
+    "error": "VALIDATION_FAILED",
+    "violations": violations,
+  });
+  Ok(warp::reply::with_status(warp::reply::json(&body), http::StatusCode::UNPROCESSABLE_ENTITY).into_response())
+}
+
+fn handle_payload_too_large(msg: &str) -> Result<warp::reply::Response, std::convert::Infallible> {
+  let body = json!({
+    "error": "PAYLOAD_TOO_LARGE",
+    "message": msg,
+  });
+  Ok(warp::reply::with_status(warp::reply::json(&body), http::StatusCode::PAYLOAD_TOO_LARGE).into_response())
+}
+
 fn handle_general_error(err: &error::Error) -> Result<warp::reply::WithStatus<&'static str>, std::convert::Infallible> {
   match err {
     error::Error::NotFoundError(_) => Ok(warp::reply::with_status("NOT_FOUND", http::StatusCode::NOT_FOUND)),
@@ -283,8 +741,13 @@ async fn handle_auth(store: store::Store, root: Option<apikey::Authorization>, h
       return Ok(root);
     }
   }
-  match store.verify_authorization(key, secret).await {
-    Ok(auth) => Ok(auth),
+  // the verification is boxed since this filter is part of every route and
+  // its state would otherwise be carried by each of their futures
+  match Box::pin(store.verify_authorization(key, secret)).await {
+    Ok(auth) => match auth.check_validity(chrono::Utc::now()) {
+      Ok(_) => Ok(auth),
+      Err(err) => Err(err.into()),
+    },
     Err(err) => match err {
       store::error::Error::NotFoundError => Err(model::apikey::Error::Unauthorized("Invalid API Key".to_string()).into()),
       err => Err(err.into()),
@@ -304,21 +767,34 @@ async fn handle_fetch_account(account_id: i64, store: store::Store, auth: apikey
   }
 }
 
-async fn handle_create_authorization(account_id: i64, store: store::Store, auth: apikey::Authorization, scopes: acl::scope::Scopes) -> Result<impl warp::Reply, warp::Rejection> {
+async fn handle_fetch_account_limits(account_id: i64, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Account)?;
+  match store.fetch_account_limits(account_id).await {
+    Ok(limits) => Ok(warp::reply::with_status(limits, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_store_account_limits(account_id: i64, store: store::Store, auth: apikey::Authorization, overrides: model::limits::Overrides) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::System)?;
+  match store.store_account_limits(account_id, &overrides).await {
+    Ok(limits) => Ok(warp::reply::with_status(limits, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_create_authorization(account_id: i64, store: store::Store, auth: apikey::Authorization, max_lifetime: Option<chrono::Duration>, grant: apikey::Grant) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::ACL)?;
   let (key, secret) = apikey::gen_apikey();
-  let create = apikey::Authorization{
-    account_id: Some(account_id),
-    scopes: scopes,
-    api_key: apikey::ApiKey{
-      id: 0,
-      key: key,
-      secret: secret,
-    },
+  let api_key = apikey::ApiKey{
+    id: 0,
+    key: key,
+    secret: Some(secret),
   };
+  let create = grant.authorize(account_id, api_key, chrono::Utc::now(), max_lifetime)?;
   match store.store_authorization(&create).await {
     Ok(create) => Ok(warp::reply::with_status(create, http::StatusCode::OK)),
-    Err(err) => return Err(err.into()),
+    Err(err) => Err(err.into()),
   }
 }
 
@@ -346,72 +822,397 @@ async fn handle_list_authorizations(account_id: i64, store: store::Store, auth:
   }
 }
 
-async fn handle_fetch_entry(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+async fn handle_create_entry(account_id: i64, store: store::Store, auth: apikey::Authorization, create: model::entry::Create) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  let entry = create.entry(account_id)?;
+  match store.create_entry(&entry).await {
+    Ok(entry) => Ok(warp::reply::with_status(entry, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_list_entries(account_id: i64, store: store::Store, auth: apikey::Authorization, filter: model::entry::Filter) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.list_entries(account_id, &filter).await {
+    Ok(entries) => Ok(warp::reply::with_status(entries, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+// Produce the representation of an entry requested by the client, which
+// may include the attributes of the token that produced it.
+async fn reply_entry(store: &store::Store, entry: model::entry::Entry, opts: &model::output::Options) -> Result<warp::reply::Response, warp::Rejection> {
+  let attrs = match (opts.wants_attrs(), &entry.token) {
+    (true, Some(token)) => match store.fetch_token_attrs(entry.creator_id, entry.key.to_owned(), token.to_owned()).await {
+      Ok(v) => v.attrs,
+      Err(err) => return Err(err.into()),
+    },
+    _ => collections::HashMap::new(),
+  };
+  match opts.reply(entry, &attrs) {
+    Ok(res) => Ok(res),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_fetch_entry(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, opts: model::output::Options) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
   let entry = match store.fetch_entry(account_id, key).await {
     Ok(v) => v,
     Err(err) => return Err(err.into()),
   };
-  Ok(warp::reply::with_status(entry, http::StatusCode::OK))
+  if opts.is_json() {
+    let attrs = match store.fetch_entry_attrs(account_id, entry.key.to_owned()).await {
+      Ok(v) => v.attrs,
+      Err(err) => return Err(err.into()),
+    };
+    return Ok(warp::reply::with_status(model::series::Series::new(entry, attrs).into_response(), http::StatusCode::OK));
+  }
+  let res = reply_entry(&store, entry, &opts).await?;
+  Ok(warp::reply::with_status(res, http::StatusCode::OK))
 }
 
-async fn handle_delete_entry(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+async fn handle_delete_entry(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, del: model::entry::Deletion) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Delete, acl::scope::Resource::Series)?;
-  match store.delete_entry(account_id, key).await {
+  match store.delete_entry(account_id, key, &del).await {
     Ok(_) => Ok(warp::reply::reply()),
     Err(err) => Err(err.into()),
   }
 }
 
-async fn handle_fetch_entry_version(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+async fn handle_set_entry(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, set: model::entry::Assignment) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  if set.force { // moving a series backwards can reissue versions, treat it like a delete
+    auth.assert_allows_in_account(account_id, acl::scope::Operation::Delete, acl::scope::Resource::Series)?;
+  }
+  let entry = match store.set_entry(account_id, key, &auth.api_key.key, &set).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  Ok(warp::reply::with_status(entry, http::StatusCode::OK))
+}
+
+async fn handle_store_entry_template(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, fmt: model::entry::Formatting) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  let entry = match store.store_entry_template(account_id, key, &fmt).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  Ok(warp::reply::with_status(entry, http::StatusCode::OK))
+}
+
+async fn handle_store_entry_schema(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, schema: model::schema::Schema) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  match store.store_entry_schema(account_id, key, &schema).await {
+    Ok(schema) => Ok(warp::reply::with_status(schema, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_fetch_entry_schema(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
-  let entry = match store.fetch_entry_version(account_id, key, token).await {
+  match store.fetch_entry_schema(account_id, key).await {
+    Ok(schema) => Ok(warp::reply::with_status(schema, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_delete_entry_schema(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  match store.delete_entry_schema(account_id, key).await {
+    Ok(_) => Ok(warp::reply::reply()),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_fetch_entry_audit(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.fetch_entry_audit(account_id, key).await {
+    Ok(audit) => Ok(warp::reply::with_status(warp::reply::Response::new(json!(audit).to_string().into()), http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_create_entry_branch(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, branch: model::entry::Branch) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  let entry = match store.create_entry_branch(account_id, key, &branch).await {
     Ok(v) => v,
     Err(err) => return Err(err.into()),
   };
   Ok(warp::reply::with_status(entry, http::StatusCode::OK))
 }
 
-async fn handle_inc_entry(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+async fn handle_list_entry_branches(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, filter: model::entry::Filter) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  let prefix = match store.branch_key(account_id, &key, &filter.prefix.clone().unwrap_or_default()).await {
+    Ok(prefix) => prefix,
+    Err(err) => return Err(err.into()),
+  };
+  let filter = model::entry::Filter{
+    prefix: Some(prefix),
+    ..filter
+  };
+  match store.list_entries(account_id, &filter).await {
+    Ok(entries) => Ok(warp::reply::with_status(entries, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_fetch_entry_branch(account_id: i64, key: String, name: String, store: store::Store, auth: apikey::Authorization, opts: model::output::Options) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  model::entry::Branch::validate_name(&name)?;
+  let key = match store.branch_key(account_id, &key, &name).await {
+    Ok(key) => key,
+    Err(err) => return Err(err.into()),
+  };
+  let entry = match store.fetch_entry(account_id, key).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  let res = reply_entry(&store, entry, &opts).await?;
+  Ok(warp::reply::with_status(res, http::StatusCode::OK))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_inc_entry_branch(account_id: i64, key: String, name: String, token: String, store: store::Store, auth: apikey::Authorization, inc: model::entry::Increment, opts: model::output::Options) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
-  let entry = match store.inc_entry(account_id, key, Some(token)).await {
+  model::entry::Branch::validate_name(&name)?;
+  let entry = match store.inc_entry_branch(account_id, key, name, token, &inc).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  let res = reply_entry(&store, entry, &opts).await?;
+  Ok(warp::reply::with_status(res, http::StatusCode::OK))
+}
+
+async fn handle_store_entry_alias(account_id: i64, key: String, alias: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  let alias = match store.store_entry_alias(account_id, key, alias).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  Ok(warp::reply::with_status(alias, http::StatusCode::OK))
+}
+
+async fn handle_fetch_entry_aliases(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.fetch_entry_aliases(account_id, key).await {
+    Ok(aliases) => Ok(warp::reply::with_status(warp::reply::Response::new(json!(aliases).to_string().into()), http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_delete_entry_alias(account_id: i64, key: String, alias: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Delete, acl::scope::Resource::Series)?;
+  match store.delete_entry_alias(account_id, key, alias).await {
+    Ok(_) => Ok(warp::reply::reply()),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_move_entry(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, mv: model::entry::Move) -> Result<impl warp::Reply, warp::Rejection> {
+  // the series is removed from the source and created in the destination
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Delete, acl::scope::Resource::Series)?;
+  auth.assert_allows_in_account(mv.account_id.unwrap_or(account_id), acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  let entry = match store.move_entry(account_id, key, &mv).await {
     Ok(v) => v,
     Err(err) => return Err(err.into()),
   };
   Ok(warp::reply::with_status(entry, http::StatusCode::OK))
 }
 
+async fn handle_restore_entry(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Delete, acl::scope::Resource::Series)?;
+  let entry = match store.restore_entry(account_id, key).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  Ok(warp::reply::with_status(entry, http::StatusCode::OK))
+}
+
+async fn handle_fetch_entry_version(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization, opts: model::output::Options) -> Result<impl warp::Reply, warp::Rejection> {
+  if model::entry::RESERVED_TOKENS.contains(&token.as_str()) {
+    return Err(warp::reject::not_found());
+  }
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  let entry = match store.fetch_entry_version(account_id, key, token).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  let res = reply_entry(&store, entry, &opts).await?;
+  Ok(warp::reply::with_status(res, http::StatusCode::OK))
+}
+
+async fn handle_fetch_entry_version_by_value(account_id: i64, key: String, value: i64, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.fetch_entry_version_by_value(account_id, key, value).await {
+    Ok(version) => Ok(warp::reply::with_status(version, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_list_entry_versions(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, filter: model::entry::VersionFilter) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.list_entry_versions(account_id, key, &filter).await {
+    Ok(versions) => Ok(warp::reply::with_status(versions, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_list_entry_versions_with_attrs(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, filter: model::entry::VersionFilter) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.list_entry_versions_with_attrs(account_id, key, &filter).await {
+    Ok(versions) => Ok(warp::reply::with_status(versions, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_query_entry_versions(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, query: model::query::Query) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.query_entry_versions(account_id, Some(key), &query).await {
+    Ok(versions) => Ok(warp::reply::with_status(versions, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_query_versions(account_id: i64, store: store::Store, auth: apikey::Authorization, query: model::query::Query) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.query_entry_versions(account_id, None, &query).await {
+    Ok(versions) => Ok(warp::reply::with_status(versions, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_inc_entry(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization, inc: model::entry::Increment, opts: model::output::Options) -> Result<impl warp::Reply, warp::Rejection> {
+  if model::entry::RESERVED_TOKENS.contains(&token.as_str()) {
+    return Err(warp::reject::not_found());
+  }
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  let entry = match store.inc_entry(account_id, key, Some(token), &inc).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  let res = reply_entry(&store, entry, &opts).await?;
+  Ok(warp::reply::with_status(res, http::StatusCode::OK))
+}
+
+async fn handle_inc_entries(account_id: i64, store: store::Store, auth: apikey::Authorization, batch: model::entry::Batch) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  match store.inc_entries(account_id, &batch.keys, batch.token, &batch.inc).await {
+    Ok(entries) => Ok(warp::reply::with_status(warp::reply::Response::new(json!(entries).to_string().into()), http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_fetch_entry_attrs(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  match store.fetch_entry_attrs(account_id, key).await {
+    Ok(attrs) => Ok(warp::reply::with_status(attrs, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_store_entry_attrs(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, if_match: Option<String>, attrs: collections::HashMap<String, serde_json::Value>) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  match store.store_entry_attrs(account_id, key, &attrs, if_match.as_deref()).await {
+    Ok(etag) => Ok(warp::reply::with_status(model::attrs::Attrs::new(attrs).with_etag(etag), http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_patch_entry_attrs(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, if_match: Option<String>, patch: serde_json::Value) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  match store.patch_entry_attrs(account_id, key, &patch, if_match.as_deref()).await {
+    Ok(attrs) => Ok(warp::reply::with_status(attrs, http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_delete_entry_attrs(account_id: i64, key: String, store: store::Store, auth: apikey::Authorization, if_match: Option<String>) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  match store.delete_entry_attrs(account_id, key, if_match.as_deref()).await {
+    Ok(_) => Ok(warp::reply::reply()),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_store_entry_attr(account_id: i64, key: String, name: String, store: store::Store, auth: apikey::Authorization, enc: model::attrs::Encoding, value: bytes::Bytes) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  let value = match String::from_utf8(value.to_vec()) {
+    Ok(value) => value,
+    Err(err) => return Err(error::Error::Utf8Error(err.utf8_error()).into()),
+  };
+  let value = match enc.value(value) {
+    Ok(value) => value,
+    Err(err) => return Err(err.into()),
+  };
+  match store.store_entry_attr(account_id, key, &name, &value).await {
+    Ok(_) => Ok(warp::reply::with_status(model::attrs::Attrs::singleton(name, value), http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
+}
+
+async fn handle_fetch_entry_attr(account_id: i64, key: String, name: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
+  let val = match store.fetch_entry_attr(account_id, key, name).await {
+    Ok(v) => v,
+    Err(err) => return Err(err.into()),
+  };
+  Ok(warp::reply::with_status(model::attrs::Value::new(val), http::StatusCode::OK))
+}
+
+async fn handle_delete_entry_attr(account_id: i64, key: String, name: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  match store.delete_entry_attr(account_id, key, name).await {
+    Ok(_) => Ok(warp::reply::reply()),
+    Err(err) => Err(err.into()),
+  }
+}
+
 async fn handle_fetch_token_attrs(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Read, acl::scope::Resource::Series)?;
   let attrs = match store.fetch_token_attrs(account_id, key, token).await {
     Ok(v) => v,
     Err(err) => return Err(err.into()),
   };
-  Ok(warp::reply::with_status(model::attrs::Attrs::new(attrs), http::StatusCode::OK))
+  Ok(warp::reply::with_status(attrs, http::StatusCode::OK))
+}
+
+async fn handle_store_token_attrs(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization, if_match: Option<String>, attrs: collections::HashMap<String, serde_json::Value>) -> Result<impl warp::Reply, warp::Rejection> {
+  auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
+  match store.store_token_attrs(account_id, key, token, &attrs, if_match.as_deref()).await {
+    Ok(etag) => Ok(warp::reply::with_status(model::attrs::Attrs::new(attrs).with_etag(etag), http::StatusCode::OK)),
+    Err(err) => Err(err.into()),
+  }
 }
 
-async fn handle_store_token_attrs(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization, attrs: collections::HashMap<String, String>) -> Result<impl warp::Reply, warp::Rejection> {
+async fn handle_patch_token_attrs(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization, if_match: Option<String>, patch: serde_json::Value) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
-  match store.store_token_attrs(account_id, key, token, &attrs).await {
-    Ok(_) => Ok(warp::reply::with_status(model::attrs::Attrs::new(attrs), http::StatusCode::OK)),
+  match store.patch_token_attrs(account_id, key, token, &patch, if_match.as_deref()).await {
+    Ok(attrs) => Ok(warp::reply::with_status(attrs, http::StatusCode::OK)),
     Err(err) => Err(err.into()),
   }
 }
 
-async fn handle_delete_token_attrs(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
+async fn handle_delete_token_attrs(account_id: i64, key: String, token: String, store: store::Store, auth: apikey::Authorization, if_match: Option<String>) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
-  match store.delete_token_attrs(account_id, key, token).await {
+  match store.delete_token_attrs(account_id, key, token, if_match.as_deref()).await {
     Ok(_) => Ok(warp::reply::reply()),
     Err(err) => Err(err.into()),
   }
 }
 
-async fn handle_store_token_attr(account_id: i64, key: String, token: String, name: String, store: store::Store, auth: apikey::Authorization, value: bytes::Bytes) -> Result<impl warp::Reply, warp::Rejection> {
+#[allow(clippy::too_many_arguments)]
+async fn handle_store_token_attr(account_id: i64, key: String, token: String, name: String, store: store::Store, auth: apikey::Authorization, enc: model::attrs::Encoding, value: bytes::Bytes) -> Result<impl warp::Reply, warp::Rejection> {
   auth.assert_allows_in_account(account_id, acl::scope::Operation::Write, acl::scope::Resource::Series)?;
   let value = match String::from_utf8(value.to_vec()) {
     Ok(value) => value,
     Err(err) => return Err(error::Error::Utf8Error(err.utf8_error()).into()),
   };
+  let value = match enc.value(value) {
+    Ok(value) => value,
+    Err(err) => return Err(err.into()),
+  };
   match store.store_token_attr(account_id, key, token, &name, &value).await {
     Ok(_) => Ok(warp::reply::with_status(model::attrs::Attrs::singleton(name, value), http::StatusCode::OK)),
     Err(err) => Err(err.into()),
@@ -424,7 +1225,7 @@ async fn handle_fetch_token_attr(account_id: i64, key: String, token: String, na
     Ok(v) => v,
     Err(err) => return Err(err.into()),
   };
-  Ok(warp::reply::with_status(val, http::StatusCode::OK))
+  Ok(warp::reply::with_status(model::attrs::Value::new(val), http::StatusCode::OK))
 }
 
 async fn handle_delete_token_attr(account_id: i64, key: String, token: String, name: String, store: store::Store, auth: apikey::Authorization) -> Result<impl warp::Reply, warp::Rejection> {
@@ -0,0 +1,34 @@
+use crate::store;
+
+use chrono;
+use warp;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use tokio_postgres;
+
+// An alternate key which refers to a series. Series routes accept an alias
+// wherever they accept a series key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alias {
+  pub alias: String,
+  pub creator_id: i64,
+  pub key: String,
+  pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Alias {
+  pub fn unmarshal(row: &tokio_postgres::Row) -> Result<Alias, store::error::Error> {
+    Ok(Alias{
+      alias: row.try_get(0)?,
+      creator_id: row.try_get(1)?,
+      key: row.try_get(2)?,
+      created_at: row.try_get(3)?,
+    })
+  }
+}
+
+impl warp::Reply for Alias {
+  fn into_response(self) -> warp::reply::Response {
+    warp::reply::Response::new(json!(self).to_string().into())
+  }
+}
@@ -1,7 +1,10 @@
 use std::fmt;
 use std::str;
 
+use chrono;
+use bcrypt;
 use rand::{self, Rng};
+use subtle::ConstantTimeEq;
 use serde::{Serialize, Deserialize};
 use serde_json::json;
 use tokio_postgres;
@@ -11,10 +14,19 @@ use crate::acl::scope;
 
 const AUTH_TYPE_BASIC: &str = "Basic";
 
+// The bcrypt cost used to hash API Key secrets. This matches the cost used
+// to hash existing secrets when they were migrated. Secrets are long random
+// strings, so the hash only needs to make searching a leaked hash costly.
+// Every authenticated request verifies the secret at this cost, which takes
+// in the order of 50ms of CPU time on the blocking thread pool.
+const SECRET_HASH_COST: u32 = 10;
+
 #[derive(Debug, PartialEq)]
 pub enum Error {
   Unauthorized(String),
   Forbidden(String),
+  Expired(String),
+  NotYetValid(String),
   Utf8Error(std::str::Utf8Error),
   DecodeBase64Error(base64::DecodeError),
 }
@@ -38,6 +50,8 @@ impl fmt::Display for Error {
     match self {
       Self::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
       Self::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+      Self::Expired(msg) => write!(f, "Expired: {}", msg),
+      Self::NotYetValid(msg) => write!(f, "Not yet valid: {}", msg),
       Self::Utf8Error(err) => err.fmt(f),
       Self::DecodeBase64Error(err) => err.fmt(f),
     }
@@ -56,6 +70,31 @@ pub fn gen_apikey() -> (String, String) {
   (random_string(24), random_string(128))
 }
 
+// Hash an API Key secret for storage. Note that bcrypt only considers the
+// first 72 bytes of a secret, which is still far more entropy than needed.
+pub fn hash_secret(secret: &str) -> Result<String, store::error::Error> {
+  Ok(bcrypt::hash(secret, SECRET_HASH_COST)?)
+}
+
+// Determine whether a stored secret is a hash. Secrets stored before they
+// were hashed remain plaintext until the service hashes them at startup,
+// unless they were hashed when the database was upgraded.
+pub fn is_hashed(stored: &str) -> bool {
+  stored.len() == 60 && stored.starts_with("$2")
+}
+
+// Determine whether a secret matches a stored secret, which is either a hash
+// or, for secrets which have not been hashed yet, plaintext. Either way the
+// comparison is performed in constant time and a malformed hash never
+// matches.
+pub fn verify_secret(secret: &str, stored: &str) -> bool {
+  if is_hashed(stored) {
+    bcrypt::verify(secret, stored).unwrap_or(false)
+  }else{
+    stored.as_bytes().ct_eq(secret.as_bytes()).into()
+  }
+}
+
 pub fn parse_apikey(data: &str) -> Result<(String, String), Error> {
   let parts: Vec<&str> = data.split(' ').collect();
   if parts.len() != 2 {
@@ -88,11 +127,14 @@ pub trait AccessControl {
   fn assert_allows_in_account(&self, account_id: i64, op: scope::Operation, rc: scope::Resource) -> Result<(), Error>;
 }
 
+// An API Key. The secret is only known when the key is created, since only
+// a hash of it is stored; it is never available for a key which is read.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ApiKey {
   pub id: i64,
   pub key: String,
-  pub secret: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub secret: Option<String>,
 }
 
 impl ApiKey {
@@ -100,7 +142,7 @@ impl ApiKey {
     Ok(Self{
       id: row.try_get(0)?,
       key: row.try_get(1)?,
-      secret: row.try_get(2)?,
+      secret: None,
     })
   }
   
@@ -115,7 +157,10 @@ impl ApiKey {
 
 impl Authenticate for ApiKey {
   fn auth(&self, key: &str, secret: &str) -> bool {
-    self.key == key && self.secret == secret
+    match &self.secret {
+      Some(expect) => self.key == key && bool::from(expect.as_bytes().ct_eq(secret.as_bytes())),
+      None => false,
+    }
   }
 }
 
@@ -125,11 +170,71 @@ impl warp::Reply for ApiKey {
   }
 }
 
+// The body of a request to create an API Key. This is either a bare list
+// of scopes, which creates a key that never expires, or the scopes along
+// with the period during which the key is valid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Grant {
+  Scopes(scope::Scopes),
+  Spec{
+    scopes: scope::Scopes,
+    #[serde(default)]
+    not_before: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+  },
+}
+
+impl Grant {
+  // Produce an Authorization for this grant in the specified account. When
+  // a maximum lifetime is provided, keys which would otherwise never expire
+  // expire after that lifetime and longer validity periods are rejected.
+  pub fn authorize(self, account_id: i64, api_key: ApiKey, now: chrono::DateTime<chrono::Utc>, max_lifetime: Option<chrono::Duration>) -> Result<Authorization, store::error::Error> {
+    let (scopes, not_before, expires_at) = match self {
+      Grant::Scopes(scopes) => (scopes, None, None),
+      Grant::Spec{scopes, not_before, expires_at} => (scopes, not_before, expires_at),
+    };
+    if let (Some(not_before), Some(expires_at)) = (not_before, expires_at) {
+      if expires_at <= not_before {
+        return Err(store::error::Error::InvalidOperationError("API Key must expire after it becomes valid".to_string()));
+      }
+    }
+    if let Some(expires_at) = expires_at {
+      if expires_at <= now {
+        return Err(store::error::Error::InvalidOperationError("API Key must not already be expired".to_string()));
+      }
+    }
+    let expires_at = match max_lifetime {
+      Some(max_lifetime) => {
+        let limit = not_before.unwrap_or(now).max(now) + max_lifetime;
+        match expires_at {
+          Some(expires_at) if expires_at > limit => return Err(store::error::Error::InvalidOperationError(format!("API Key cannot be valid for more than {} days", max_lifetime.num_days()))),
+          Some(expires_at) => Some(expires_at),
+          None => Some(limit),
+        }
+      },
+      None => expires_at,
+    };
+    Ok(Authorization{
+      account_id: Some(account_id),
+      scopes: scopes,
+      api_key: api_key,
+      not_before: not_before,
+      expires_at: expires_at,
+    })
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Authorization {
   pub account_id: Option<i64>,
   pub scopes: scope::Scopes,
   pub api_key: ApiKey,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub not_before: Option<chrono::DateTime<chrono::Utc>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl Authorization {
@@ -144,8 +249,25 @@ impl Authorization {
       account_id: Some(row.try_get(3)?),
       scopes: scope::Scopes::new(scope_set),
       api_key: ApiKey::unmarshal(row)?,
+      not_before: row.try_get(5)?,
+      expires_at: row.try_get(6)?,
     })
   }
+  
+  // Determine whether this authorization may be used at the specified time.
+  pub fn check_validity(&self, now: chrono::DateTime<chrono::Utc>) -> Result<(), Error> {
+    if let Some(not_before) = self.not_before {
+      if now < not_before {
+        return Err(Error::NotYetValid(format!("API Key is not valid until {}", not_before)));
+      }
+    }
+    if let Some(expires_at) = self.expires_at {
+      if now >= expires_at {
+        return Err(Error::Expired(format!("API Key expired at {}", expires_at)));
+      }
+    }
+    Ok(())
+  }
 }
 
 impl Authenticate for Authorization {
@@ -177,3 +299,77 @@ impl warp::Reply for Authorization {
     warp::reply::Response::new(json!(self).to_string().into())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+  
+  fn api_key() -> ApiKey {
+    ApiKey{id: 0, key: "key".to_string(), secret: Some("secret".to_string())}
+  }
+  
+  #[test]
+  fn secret() {
+    let hash = hash_secret("secret").unwrap();
+    assert_ne!("secret", hash);
+    assert!(verify_secret("secret", &hash));
+    assert!(!verify_secret("secreT", &hash));
+    assert!(verify_secret("secret", "secret")); // not hashed yet
+    assert!(!verify_secret("secreT", "secret"));
+    assert!(!verify_secret("secret", "$2b$10$malformed"));
+    assert!(is_hashed(&hash));
+    assert!(!is_hashed("secret"));
+    // produced by crypt('secret', gen_salt('bf', 10)) when migrating existing secrets
+    assert!(verify_secret("secret", "$2a$10$AyheZLWnLIzWDx9fA4peV.WMPWuXPp9UW6NJunRagjm6aGGXhMZci"));
+    assert!(api_key().auth("key", "secret"));
+    assert!(!api_key().auth("key", "secreT"));
+    assert!(!ApiKey{secret: None, ..api_key()}.auth("key", "secret"));
+  }
+  
+  #[test]
+  fn grant() {
+    let now = chrono::Utc.ymd(2022, 5, 1).and_hms(0, 0, 0);
+    let days = chrono::Duration::days;
+    
+    let grant: Grant = serde_json::from_str(r#"["read:series"]"#).unwrap();
+    let auth = grant.clone().authorize(1, api_key(), now, None).unwrap();
+    assert_eq!((Some(1), None, None), (auth.account_id, auth.not_before, auth.expires_at));
+    let auth = grant.authorize(1, api_key(), now, Some(days(90))).unwrap();
+    assert_eq!(Some(now + days(90)), auth.expires_at);
+    
+    let grant: Grant = serde_json::from_str(r#"{"scopes": ["read:series"], "not_before": "2022-06-01T00:00:00Z"}"#).unwrap();
+    let auth = grant.authorize(1, api_key(), now, Some(days(90))).unwrap();
+    assert_eq!(Some(now + days(31 + 90)), auth.expires_at);
+    
+    let grant: Grant = serde_json::from_str(r#"{"scopes": ["read:series"], "expires_at": "2022-09-01T00:00:00Z"}"#).unwrap();
+    assert!(grant.clone().authorize(1, api_key(), now, None).is_ok());
+    assert!(grant.authorize(1, api_key(), now, Some(days(90))).is_err());
+    
+    let grant: Grant = serde_json::from_str(r#"{"scopes": ["read:series"], "not_before": "2022-06-01T00:00:00Z", "expires_at": "2022-06-01T00:00:00Z"}"#).unwrap();
+    assert!(matches!(grant.authorize(1, api_key(), now, None), Err(store::error::Error::InvalidOperationError(_))));
+    let grant: Grant = serde_json::from_str(r#"{"scopes": ["read:series"], "not_before": "2022-06-01T00:00:00Z", "expires_at": "2022-05-15T00:00:00Z"}"#).unwrap();
+    assert!(matches!(grant.authorize(1, api_key(), now, None), Err(store::error::Error::InvalidOperationError(_))));
+    
+    let grant: Grant = serde_json::from_str(r#"{"scopes": ["read:series"], "expires_at": "2022-04-01T00:00:00Z"}"#).unwrap();
+    assert!(matches!(grant.authorize(1, api_key(), now, None), Err(store::error::Error::InvalidOperationError(_))));
+    let grant: Grant = serde_json::from_str(r#"{"scopes": ["read:series"], "expires_at": "2022-05-01T00:00:00Z"}"#).unwrap();
+    assert!(matches!(grant.authorize(1, api_key(), now, None), Err(store::error::Error::InvalidOperationError(_))));
+  }
+  
+  #[test]
+  fn validity() {
+    let now = chrono::Utc.ymd(2022, 5, 1).and_hms(0, 0, 0);
+    let auth = Authorization{
+      account_id: Some(1),
+      scopes: scope::Scopes::new(Vec::new()),
+      api_key: api_key(),
+      not_before: Some(now),
+      expires_at: Some(now + chrono::Duration::days(1)),
+    };
+    assert!(auth.check_validity(now).is_ok());
+    assert!(matches!(auth.check_validity(now - chrono::Duration::seconds(1)), Err(Error::NotYetValid(_))));
+    assert!(matches!(auth.check_validity(now + chrono::Duration::days(1)), Err(Error::Expired(_))));
+  }
+  
+}
@@ -1,22 +1,234 @@
+use std::fmt;
 use std::collections;
 
-use warp;
-use serde_json::json;
+use crate::store;
+
+use sha2::Digest;
+use warp::{self, http};
+use serde::Deserialize;
+use serde_json::{self, json};
+
+// The JSON type of an attribute value. Strings are stored verbatim, so that
+// they remain legible in the database; every other type is stored as JSON.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+  String, Number, Boolean, Array, Object,
+}
+
+impl Kind {
+  pub fn parse(s: &str) -> Result<Kind, store::error::Error> {
+    match s.trim().to_lowercase().as_ref() {
+      "string"  => Ok(Kind::String),
+      "number"  => Ok(Kind::Number),
+      "boolean" => Ok(Kind::Boolean),
+      "array"   => Ok(Kind::Array),
+      "object"  => Ok(Kind::Object),
+      _         => Err(store::error::Error::MarshalError),
+    }
+  }
+
+  pub fn of(value: &serde_json::Value) -> Option<Kind> {
+    match value {
+      serde_json::Value::String(_) => Some(Kind::String),
+      serde_json::Value::Number(_) => Some(Kind::Number),
+      serde_json::Value::Bool(_)   => Some(Kind::Boolean),
+      serde_json::Value::Array(_)  => Some(Kind::Array),
+      serde_json::Value::Object(_) => Some(Kind::Object),
+      serde_json::Value::Null      => None,
+    }
+  }
+}
+
+impl fmt::Display for Kind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Kind::String  => write!(f, "string"),
+      Kind::Number  => write!(f, "number"),
+      Kind::Boolean => write!(f, "boolean"),
+      Kind::Array   => write!(f, "array"),
+      Kind::Object  => write!(f, "object"),
+    }
+  }
+}
+
+// Encode an attribute value for storage as its kind and text. Null values
+// cannot be stored.
+pub fn encode(name: &str, value: &serde_json::Value) -> Result<(Kind, String), store::error::Error> {
+  match value {
+    serde_json::Value::String(s) => Ok((Kind::String, s.to_owned())),
+    value => match Kind::of(value) {
+      Some(kind) => Ok((kind, value.to_string())),
+      None => Err(store::error::Error::InvalidOperationError(format!("Attribute cannot be null: {:?}", name))),
+    },
+  }
+}
+
+// Decode a stored attribute value from its kind and text.
+pub fn decode(kind: &str, text: String) -> Result<serde_json::Value, store::error::Error> {
+  match Kind::parse(kind)? {
+    Kind::String => Ok(serde_json::Value::String(text)),
+    _ => match serde_json::from_str(&text) {
+      Ok(value) => Ok(value),
+      Err(_) => Err(store::error::Error::MarshalError),
+    },
+  }
+}
+
+// Format an attribute value as text. Strings are their own text, and every
+// other type is represented as JSON.
+pub fn text(value: &serde_json::Value) -> String {
+  match value {
+    serde_json::Value::String(s) => s.to_owned(),
+    value => value.to_string(),
+  }
+}
+
+// Options for storing a single attribute. By default the request body is
+// stored as a string; a typed attribute is provided as a JSON value.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Encoding {
+  #[serde(default)]
+  pub typed: bool,
+}
+
+impl Encoding {
+  pub fn value(&self, body: String) -> Result<serde_json::Value, store::error::Error> {
+    if !self.typed {
+      return Ok(serde_json::Value::String(body));
+    }
+    match serde_json::from_str(&body) {
+      Ok(value) => Ok(value),
+      Err(err) => Err(store::error::Error::InvalidOperationError(format!("Attribute is not valid JSON: {}", err))),
+    }
+  }
+}
+
+// Produce the entity tag of an attribute set from the name, kind and stored
+// value of each attribute. The tag depends only on the content of the set, so
+// it changes with every modification that has an effect, however close in time
+// to the previous one. A set without attributes is always tagged "0-0".
+pub fn etag(attrs: &[(String, String, String)]) -> String {
+  if attrs.is_empty() {
+    return "\"0-0\"".to_string();
+  }
+  let mut sorted: Vec<&(String, String, String)> = attrs.iter().collect();
+  sorted.sort();
+  let mut hasher = sha2::Sha256::new();
+  for (name, kind, value) in sorted {
+    for field in [name, kind, value] {
+      hasher.update((field.len() as u64).to_be_bytes());
+      hasher.update(field.as_bytes());
+    }
+  }
+  let digest = format!("{:x}", hasher.finalize());
+  format!("\"{}-{}\"", attrs.len(), &digest[..32])
+}
+
+// Apply a JSON Merge Patch (RFC 7396) to a value. Null members of the
+// patch delete the corresponding members of the target and other members
+// are merged recursively; any other patch replaces the target.
+pub fn merge_patch(target: Option<&serde_json::Value>, patch: &serde_json::Value) -> serde_json::Value {
+  match patch {
+    serde_json::Value::Object(patch) => {
+      let mut res = match target {
+        Some(serde_json::Value::Object(target)) => target.clone(),
+        _ => serde_json::Map::new(),
+      };
+      for (name, value) in patch {
+        if value.is_null() {
+          res.remove(name);
+        }else{
+          let value = merge_patch(res.get(name), value);
+          res.insert(name.to_owned(), value);
+        }
+      }
+      serde_json::Value::Object(res)
+    },
+    patch => patch.clone(),
+  }
+}
+
+// The effect of applying a merge patch to an attribute set: the merged
+// set, the attributes which are new or have a new value, the existing
+// attributes which are retained and the names of those which are removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changes {
+  pub merged: collections::HashMap<String, serde_json::Value>,
+  pub changed: collections::HashMap<String, serde_json::Value>,
+  pub retained: collections::HashMap<String, serde_json::Value>,
+  pub removed: Vec<String>,
+}
+
+impl Changes {
+  pub fn patch(existing: &collections::HashMap<String, serde_json::Value>, patch: &serde_json::Value) -> Result<Changes, store::error::Error> {
+    if !patch.is_object() {
+      return Err(store::error::Error::InvalidOperationError("Patch must be an object".to_string()));
+    }
+    let merged: collections::HashMap<String, serde_json::Value> = match merge_patch(Some(&json!(existing)), patch) {
+      serde_json::Value::Object(merged) => merged.into_iter().collect(),
+      _ => return Err(store::error::Error::MarshalError),
+    };
+    let changed = merged.iter()
+      .filter(|(name, value)| existing.get(*name) != Some(value))
+      .map(|(name, value)| (name.to_owned(), value.clone()))
+      .collect();
+    let retained = existing.iter()
+      .filter(|(name, _)| merged.contains_key(*name))
+      .map(|(name, value)| (name.to_owned(), value.clone()))
+      .collect();
+    let mut removed: Vec<String> = existing.keys()
+      .filter(|name| !merged.contains_key(*name))
+      .map(|name| name.to_owned())
+      .collect();
+    removed.sort();
+    Ok(Changes{
+      merged: merged,
+      changed: changed,
+      retained: retained,
+      removed: removed,
+    })
+  }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Attrs {
-  pub attrs: collections::HashMap<String, String>,
+  pub attrs: collections::HashMap<String, serde_json::Value>,
+  pub etag: Option<String>,
 }
 
 impl Attrs {
-  pub fn new(attrs: collections::HashMap<String, String>) -> Attrs {
+  pub fn new(attrs: collections::HashMap<String, serde_json::Value>) -> Attrs {
     Attrs{
       attrs: attrs,
+      etag: None,
+    }
+  }
+
+  pub fn with_etag(self, etag: String) -> Attrs {
+    Attrs{
+      etag: Some(etag),
+      ..self
+    }
+  }
+
+  // Check an If-Match precondition against the entity tag of this set.
+  // The wildcard matches any set which has at least one attribute.
+  pub fn check_precondition(&self, if_match: Option<&str>) -> Result<(), store::error::Error> {
+    let if_match = match if_match {
+      Some(if_match) => if_match,
+      None => return Ok(()),
+    };
+    let etag = self.etag.as_deref().unwrap_or_default();
+    for tag in if_match.split(',').map(|t| t.trim()) {
+      if (tag == "*" && self.attrs.len() > 0) || tag == etag {
+        return Ok(());
+      }
     }
+    Err(store::error::Error::PreconditionFailedError(format!("Attributes have been modified; current entity tag is {}", etag)))
   }
 
-  pub fn singleton(name: String, value: String) -> Attrs {
-    let mut map: collections::HashMap<String, String> = collections::HashMap::new();
+  pub fn singleton(name: String, value: serde_json::Value) -> Attrs {
+    let mut map: collections::HashMap<String, serde_json::Value> = collections::HashMap::new();
     map.insert(name, value);
     Attrs::new(map)
   }
@@ -24,6 +236,109 @@ impl Attrs {
 
 impl warp::Reply for Attrs {
   fn into_response(self) -> warp::reply::Response {
-    warp::reply::Response::new(json!(self.attrs).to_string().into())
+    let mut res = warp::reply::Response::new(json!(self.attrs).to_string().into());
+    if let Some(etag) = self.etag {
+      if let Ok(etag) = http::HeaderValue::from_str(&etag) {
+        res.headers_mut().insert(http::header::ETAG, etag);
+      }
+    }
+    res
+  }
+}
+
+// A single attribute value. Strings are represented as plain text, as they
+// always have been, and every other type is represented as JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+  pub value: serde_json::Value,
+}
+
+impl Value {
+  pub fn new(value: serde_json::Value) -> Value {
+    Value{
+      value: value,
+    }
   }
 }
+
+impl warp::Reply for Value {
+  fn into_response(self) -> warp::reply::Response {
+    match self.value {
+      serde_json::Value::String(s) => s.into_response(),
+      value => warp::reply::json(&value).into_response(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn encode_decode() {
+    for value in &[json!("1.0"), json!(42), json!(1.5), json!(true), json!([1, "a"]), json!({"a": {"b": null}})] {
+      let (kind, text) = encode("a", value).unwrap();
+      assert_eq!(Some(kind), Kind::of(value));
+      assert_eq!(*value, decode(&kind.to_string(), text).unwrap());
+    }
+    assert_eq!(("string".to_string(), "1.0".to_string()), encode("a", &json!("1.0")).map(|(k, t)| (k.to_string(), t)).unwrap());
+    assert_eq!(("number".to_string(), "42".to_string()), encode("a", &json!(42)).map(|(k, t)| (k.to_string(), t)).unwrap());
+    assert!(encode("a", &json!(null)).is_err());
+    assert!(decode("number", "nope".to_string()).is_err());
+  }
+
+  #[test]
+  fn merge() {
+    let target = json!({"a": "b", "c": {"d": "e", "f": "g"}, "h": [1, 2]});
+    let patch = json!({"a": "z", "c": {"f": null, "i": 1}, "h": [3], "j": null});
+    assert_eq!(json!({"a": "z", "c": {"d": "e", "i": 1}, "h": [3]}), merge_patch(Some(&target), &patch));
+    assert_eq!(json!({"a": 1}), merge_patch(None, &json!({"a": 1, "b": null})));
+    assert_eq!(json!(["x"]), merge_patch(Some(&target), &json!(["x"])));
+  }
+
+  #[test]
+  fn changes() {
+    let existing: collections::HashMap<String, serde_json::Value> = serde_json::from_value(json!({"a": "b", "c": 1, "d": {"e": true}})).unwrap();
+    let changes = Changes::patch(&existing, &json!({"a": "b", "c": null, "d": {"f": 2}, "g": "h"})).unwrap();
+    assert_eq!(json!({"a": "b", "d": {"e": true, "f": 2}, "g": "h"}), json!(changes.merged));
+    assert_eq!(json!({"d": {"e": true, "f": 2}, "g": "h"}), json!(changes.changed));
+    assert_eq!(json!({"a": "b", "d": {"e": true}}), json!(changes.retained));
+    assert_eq!(vec!["c".to_string()], changes.removed);
+    assert!(Changes::patch(&existing, &json!(["a"])).is_err());
+  }
+
+  #[test]
+  fn precondition() {
+    let set = vec![("a".to_string(), "string".to_string(), "b".to_string())];
+    let attrs = Attrs::singleton("a".to_string(), json!("b")).with_etag(etag(&set));
+    assert!(attrs.check_precondition(None).is_ok());
+    assert!(attrs.check_precondition(Some("*")).is_ok());
+    assert!(attrs.check_precondition(Some(&etag(&set))).is_ok());
+    assert!(attrs.check_precondition(Some(&format!("\"nope\", {}", etag(&set)))).is_ok());
+    assert!(attrs.check_precondition(Some(&etag(&[("a".to_string(), "string".to_string(), "c".to_string())]))).is_err());
+    assert!(attrs.check_precondition(Some(&etag(&[]))).is_err());
+
+    let attrs = Attrs::new(collections::HashMap::new()).with_etag(etag(&[]));
+    assert_eq!("\"0-0\"", etag(&[]));
+    assert!(attrs.check_precondition(Some("*")).is_err());
+    assert!(attrs.check_precondition(Some("\"0-0\"")).is_ok());
+  }
+
+  #[test]
+  fn etags() {
+    let a = ("a".to_string(), "string".to_string(), "1".to_string());
+    let b = ("b".to_string(), "number".to_string(), "1".to_string());
+    assert_eq!(etag(&[a.clone(), b.clone()]), etag(&[b.clone(), a.clone()]));
+    assert!(etag(&[a.clone(), b.clone()]).starts_with("\"2-"));
+    assert_ne!(etag(&[a.clone()]), etag(&[("a".to_string(), "number".to_string(), "1".to_string())]));
+    assert_ne!(etag(&[("ab".to_string(), "string".to_string(), "c".to_string())]), etag(&[("a".to_string(), "string".to_string(), "bc".to_string())]));
+  }
+
+  #[test]
+  fn encoding() {
+    assert_eq!(json!("42"), Encoding{typed: false}.value("42".to_string()).unwrap());
+    assert_eq!(json!(42), Encoding{typed: true}.value("42".to_string()).unwrap());
+    assert!(Encoding{typed: true}.value("First one!".to_string()).is_err());
+  }
+
+}
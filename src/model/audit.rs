@@ -0,0 +1,51 @@
+use crate::store;
+
+use chrono;
+use warp;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use tokio_postgres;
+
+// A record of a manual change to the value of a series, along with the API
+// key that made it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Audit {
+  pub id: i64,
+  pub key: String,
+  pub creator_id: i64,
+  pub actor: String,
+  pub previous_value: i64,
+  pub value: i64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub previous_version: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub version: Option<String>,
+  pub forced: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub reason: Option<String>,
+  pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Audit {
+  pub fn unmarshal(row: &tokio_postgres::Row) -> Result<Audit, store::error::Error> {
+    Ok(Audit{
+      id: row.try_get(0)?,
+      key: row.try_get(1)?,
+      creator_id: row.try_get(2)?,
+      actor: row.try_get(3)?,
+      previous_value: row.try_get(4)?,
+      value: row.try_get(5)?,
+      previous_version: row.try_get(6)?,
+      version: row.try_get(7)?,
+      forced: row.try_get(8)?,
+      reason: row.try_get(9)?,
+      created_at: row.try_get(10)?,
+    })
+  }
+}
+
+impl warp::Reply for Audit {
+  fn into_response(self) -> warp::reply::Response {
+    warp::reply::Response::new(json!(self).to_string().into())
+  }
+}
@@ -0,0 +1,194 @@
+use std::fmt;
+
+use crate::store;
+
+use chrono::{self, Datelike};
+use chrono_tz;
+use serde::{Serialize, Deserialize};
+
+const MAX_UTC_OFFSET: i32 = 24 * 60; // minutes
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+  Day,
+  Week,
+  #[default]
+  Month,
+  Year,
+}
+
+impl Period {
+  pub fn parse(s: &str) -> Result<Period, store::error::Error> {
+    match s.trim().to_lowercase().as_ref() {
+      "day"   => Ok(Period::Day),
+      "week"  => Ok(Period::Week),
+      "month" => Ok(Period::Month),
+      "year"  => Ok(Period::Year),
+      _       => Err(store::error::Error::MarshalError),
+    }
+  }
+
+  // Determine the date on which the period containing the provided local
+  // date began. Weeks begin on Monday.
+  pub fn start(&self, date: chrono::NaiveDate) -> chrono::NaiveDate {
+    match self {
+      Period::Day => date,
+      Period::Week => date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64),
+      Period::Month => chrono::NaiveDate::from_ymd(date.year(), date.month(), 1),
+      Period::Year => chrono::NaiveDate::from_ymd(date.year(), 1, 1),
+    }
+  }
+}
+
+impl fmt::Display for Period {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Period::Day   => write!(f, "day"),
+      Period::Week  => write!(f, "week"),
+      Period::Month => write!(f, "month"),
+      Period::Year  => write!(f, "year"),
+    }
+  }
+}
+
+// The calendar state of a CalVer series: the period its counter resets in
+// and the number of versions issued so far in the current period, which
+// began on the start date. Periods are determined in the named timezone of
+// the series if it has one, and otherwise at its fixed offset from UTC.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Calver {
+  pub period: Period,
+  pub utc_offset: i32, // a fixed offset in minutes; it does not follow daylight saving time
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub timezone: Option<chrono_tz::Tz>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub start: Option<chrono::NaiveDate>,
+  pub number: i64,
+}
+
+impl Calver {
+  pub fn new(period: Period, utc_offset: i32) -> Result<Calver, store::error::Error> {
+    if utc_offset.abs() >= MAX_UTC_OFFSET {
+      return Err(store::error::Error::InvalidOperationError(format!("UTC offset is out of range: {}", utc_offset)));
+    }
+    Ok(Calver{
+      period: period,
+      utc_offset: utc_offset,
+      timezone: None,
+      start: None,
+      number: 0,
+    })
+  }
+  
+  // Determine periods in the named IANA timezone, e.g., "Europe/Berlin",
+  // which follows daylight saving time, rather than at a fixed offset.
+  pub fn with_timezone(self, name: &str) -> Result<Calver, store::error::Error> {
+    let timezone = match name.parse::<chrono_tz::Tz>() {
+      Ok(timezone) => timezone,
+      Err(_) => return Err(store::error::Error::InvalidOperationError(format!("Unknown timezone: {:?}", name))),
+    };
+    Ok(Calver{
+      timezone: Some(timezone),
+      ..self
+    })
+  }
+  
+  // Determine the local date of the series at the provided instant.
+  pub fn date(&self, now: chrono::DateTime<chrono::Utc>) -> chrono::NaiveDate {
+    match self.timezone {
+      Some(timezone) => now.with_timezone(&timezone).naive_local().date(),
+      None => (now.naive_utc() + chrono::Duration::minutes(self.utc_offset as i64)).date(),
+    }
+  }
+
+  // Advance the counter at the provided instant. The counter resets when a
+  // new period has begun since the last version was issued. If the clock
+  // appears to have gone backwards the current period is retained, so that
+  // versions never go backwards either.
+  pub fn advance(&self, now: chrono::DateTime<chrono::Utc>) -> Calver {
+    let start = self.period.start(self.date(now));
+    let (start, number) = match self.start {
+      Some(current) if current >= start => (current, self.number + 1),
+      _ => (start, 1),
+    };
+    Calver{
+      start: Some(start),
+      number: number,
+      ..self.clone()
+    }
+  }
+
+  // Format the version, e.g., 2022.05.3 for the third version in May 2022.
+  // Daily versions include the day and weekly versions use the ISO week.
+  // There is no version until the counter has been advanced at least once.
+  pub fn version(&self) -> Option<String> {
+    let start = self.start?;
+    Some(match self.period {
+      Period::Day => format!("{}.{:02}.{:02}.{}", start.year(), start.month(), start.day(), self.number),
+      Period::Week => format!("{}.{:02}.{}", start.iso_week().year(), start.iso_week().week(), self.number),
+      Period::Month => format!("{}.{:02}.{}", start.year(), start.month(), self.number),
+      Period::Year => format!("{}.{}", start.year(), self.number),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+
+  #[test]
+  fn period_start() {
+    let date = chrono::NaiveDate::from_ymd(2022, 5, 4); // a Wednesday
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 5, 4), Period::Day.start(date));
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 5, 2), Period::Week.start(date));
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 5, 1), Period::Month.start(date));
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 1, 1), Period::Year.start(date));
+  }
+  
+  #[test]
+  fn date() {
+    let now = chrono::Utc.ymd(2022, 5, 4).and_hms(23, 30, 0);
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 5, 4), Calver::new(Period::Day, 0).unwrap().date(now));
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 5, 5), Calver::new(Period::Day, 60).unwrap().date(now));
+    
+    let now = chrono::Utc.ymd(2022, 12, 31).and_hms(23, 30, 0);
+    assert_eq!(chrono::NaiveDate::from_ymd(2023, 1, 1), Calver::new(Period::Year, 60).unwrap().date(now));
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 12, 31), Calver::new(Period::Year, -60).unwrap().date(now));
+    
+    // Berlin is an hour ahead of UTC in winter and two hours ahead in summer
+    let berlin = Calver::new(Period::Day, 0).unwrap().with_timezone("Europe/Berlin").unwrap();
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 1, 2), berlin.date(chrono::Utc.ymd(2022, 1, 1).and_hms(23, 30, 0)));
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 7, 1), berlin.date(chrono::Utc.ymd(2022, 7, 1).and_hms(21, 30, 0)));
+    assert_eq!(chrono::NaiveDate::from_ymd(2022, 7, 2), berlin.date(chrono::Utc.ymd(2022, 7, 1).and_hms(22, 30, 0)));
+    assert!(Calver::new(Period::Day, 0).unwrap().with_timezone("Mars/Olympus_Mons").is_err());
+  }
+
+  #[test]
+  fn advance() {
+    let may = chrono::Utc.ymd(2022, 5, 4).and_hms(12, 0, 0);
+    let june = chrono::Utc.ymd(2022, 6, 1).and_hms(12, 0, 0);
+
+    let c = Calver::new(Period::Month, 0).unwrap();
+    assert_eq!(None, c.version());
+    let c = c.advance(may);
+    assert_eq!(Some("2022.05.1".to_string()), c.version());
+    let c = c.advance(may);
+    assert_eq!(Some("2022.05.2".to_string()), c.version());
+    let c = c.advance(june);
+    assert_eq!(Some("2022.06.1".to_string()), c.version());
+    let c = c.advance(may); // the clock went backwards
+    assert_eq!(Some("2022.06.2".to_string()), c.version());
+  }
+
+  #[test]
+  fn version() {
+    let now = chrono::Utc.ymd(2022, 1, 2).and_hms(12, 0, 0); // ISO week 52 of 2021
+    assert_eq!(Some("2022.01.02.1".to_string()), Calver::new(Period::Day, 0).unwrap().advance(now).version());
+    assert_eq!(Some("2021.52.1".to_string()), Calver::new(Period::Week, 0).unwrap().advance(now).version());
+    assert_eq!(Some("2022.1".to_string()), Calver::new(Period::Year, 0).unwrap().advance(now).version());
+    assert!(Calver::new(Period::Day, MAX_UTC_OFFSET).is_err());
+  }
+
+}
@@ -1,16 +1,439 @@
+use std::fmt;
+
 use crate::store;
+use crate::model::semver;
+use crate::model::calver;
+use crate::model::template;
 
+use chrono;
 use warp;
 use serde::{Serialize, Deserialize};
 use serde_json::json;
 use tokio_postgres;
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+  #[default]
+  Counter,
+  Semver,
+  Calver,
+}
+
+impl Kind {
+  pub fn parse(s: &str) -> Result<Kind, store::error::Error> {
+    match s.trim().to_lowercase().as_ref() {
+      "counter" => Ok(Kind::Counter),
+      "semver"  => Ok(Kind::Semver),
+      "calver"  => Ok(Kind::Calver),
+      _         => Err(store::error::Error::MarshalError),
+    }
+  }
+}
+
+impl fmt::Display for Kind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Kind::Counter => write!(f, "counter"),
+      Kind::Semver  => write!(f, "semver"),
+      Kind::Calver  => write!(f, "calver"),
+    }
+  }
+}
+
+// A request to increment several series for the same token at once.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Batch {
+  pub token: String,
+  pub keys: Vec<String>,
+  #[serde(flatten)]
+  pub inc: Increment,
+}
+
+// A request to rename a series, move it to another account, or both.
+// Omitted fields retain their current values.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Move {
+  pub key: Option<String>,
+  pub account_id: Option<i64>,
+}
+
+impl Move {
+  // Determine the account and key a series will have after it is moved.
+  pub fn target(&self, account_id: i64, key: &str) -> Result<(i64, String), store::error::Error> {
+    let target = (
+      self.account_id.unwrap_or(account_id),
+      self.key.clone().unwrap_or_else(|| key.to_string()),
+    );
+    validate_key(&target.1)?;
+    if target.0 == account_id && target.1 == key {
+      return Err(store::error::Error::InvalidOperationError(format!("Series {:?} would not be moved", key)));
+    }
+    Ok(target)
+  }
+}
+
+// A manual assignment of the value of a series. Assignments may only move
+// a series forward unless they are forced.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Assignment {
+  pub value: i64,
+  pub semver: Option<semver::Semver>,
+  #[serde(default)]
+  pub force: bool,
+  pub reason: Option<String>,
+}
+
+impl Assignment {
+  // Produce the entry that results from applying this assignment.
+  pub fn apply(&self, entry: &Entry) -> Result<Entry, store::error::Error> {
+    if self.semver.is_some() && entry.kind != Kind::Semver {
+      return Err(store::error::Error::InvalidOperationError(format!("Cannot assign a version to a {} series", entry.kind)));
+    }
+    let semver = match self.semver {
+      Some(semver) => Some(semver),
+      None => entry.semver,
+    };
+    if !self.force && (self.value < entry.value || semver < entry.semver) {
+      return Err(store::error::Error::ConflictError(format!("Series {:?} cannot be moved backwards without force", entry.key)));
+    }
+    if let Some(max) = entry.max {
+      if self.value > max {
+        return Err(store::error::Error::LimitExceededError(format!("Series {:?} cannot exceed {}", entry.key, max)));
+      }
+    }
+    Ok(Entry{
+      token: None,
+      value: self.value,
+      semver: semver,
+      prerelease: None,
+      build: None,
+      ..entry.clone()
+    }.rendered())
+  }
+}
+
+// Parameters that describe a series to create. The start value is the
+// first value the series will produce; semantic version series produce
+// their first version by bumping the provided version.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Create {
+  pub key: String,
+  #[serde(default)]
+  pub kind: Kind,
+  pub start: Option<i64>,
+  pub step: Option<i64>,
+  pub max: Option<i64>,
+  pub semver: Option<semver::Semver>,
+  pub period: Option<calver::Period>,
+  pub utc_offset: Option<i32>,
+  pub timezone: Option<String>,
+  pub description: Option<String>,
+  pub template: Option<String>,
+}
+
+impl Create {
+  pub fn entry(&self, creator_id: i64) -> Result<Entry, store::error::Error> {
+    validate_key(&self.key)?;
+    let step = self.step.unwrap_or(1);
+    if step < 1 {
+      return Err(store::error::Error::InvalidOperationError(format!("Step must be positive: {}", step)));
+    }
+    let start = self.start.unwrap_or(step);
+    if let Some(max) = self.max {
+      if max < start {
+        return Err(store::error::Error::InvalidOperationError(format!("Maximum {} is less than start {}", max, start)));
+      }
+    }
+    let value = match start.checked_sub(step) {
+      Some(value) => value,
+      None => return Err(store::error::Error::InvalidOperationError(format!("Start is out of range: {}", start))),
+    };
+    if self.kind != Kind::Calver && (self.period.is_some() || self.utc_offset.is_some() || self.timezone.is_some()) {
+      return Err(store::error::Error::InvalidOperationError(format!("A {} series has no calendar period", self.kind)));
+    }
+    let entry = match self.kind {
+      Kind::Counter => Entry::new(&self.key, creator_id, None, value),
+      Kind::Semver => Entry::new_semver(&self.key, creator_id, None, value, self.semver.unwrap_or(semver::Semver::new(0, 0, 0))),
+      Kind::Calver => {
+        let calver = calver::Calver::new(self.period.unwrap_or_default(), self.utc_offset.unwrap_or(0))?;
+        let calver = match (&self.timezone, self.utc_offset) {
+          (Some(_), Some(_)) => return Err(store::error::Error::InvalidOperationError("A series may have a UTC offset or a timezone, but not both".to_string())),
+          (Some(timezone), None) => calver.with_timezone(timezone)?,
+          (None, _) => calver,
+        };
+        Entry::new_calver(&self.key, creator_id, None, value, calver)
+      },
+    };
+    let template = match &self.template {
+      Some(source) => Some(template::Template::parse(source)?),
+      None => None,
+    };
+    Ok(Entry{
+      step: step,
+      max: self.max,
+      description: self.description.clone(),
+      template: template,
+      ..entry
+    }.rendered())
+  }
+}
+
+// A request to create a branch of a series. A branch is a series of its
+// own, keyed by the key of its parent and the name of the branch, e.g.,
+// "app/main", with a counter that is independent of its parent.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Branch {
+  pub name: String,
+  #[serde(default)]
+  pub seed: bool,
+}
+
+impl Branch {
+  pub fn key(parent: &str, name: &str) -> String {
+    format!("{}/{}", parent, name)
+  }
+  
+  // Branch names are a single path segment; that is, non-empty and limited
+  // to [0-9A-Za-z._-].
+  pub fn validate_name(name: &str) -> Result<(), store::error::Error> {
+    if name.len() == 0 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') {
+      return Err(store::error::Error::InvalidOperationError(format!("Invalid branch name: {:?}", name)));
+    }
+    Ok(())
+  }
+  
+  // Produce the branch of the provided parent series. The branch has the
+  // same kind and configuration as its parent and, if it is seeded, starts
+  // from the current value of its parent rather than from the beginning.
+  pub fn entry(&self, parent: &Entry) -> Result<Entry, store::error::Error> {
+    Self::validate_name(&self.name)?;
+    let key = Self::key(&parent.key, &self.name);
+    let entry = if self.seed {
+      Entry{
+        key: key,
+        token: None,
+        ..parent.clone()
+      }
+    }else{
+      let calver = match &parent.calver {
+        Some(calver) => Some(calver::Calver{
+          timezone: calver.timezone,
+          ..calver::Calver::new(calver.period, calver.utc_offset)?
+        }),
+        None => None,
+      };
+      Entry{
+        key: key,
+        token: None,
+        value: 0,
+        semver: parent.semver.map(|_| semver::Semver::new(0, 0, 0)),
+        version: None,
+        calver: calver,
+        ..parent.clone()
+      }
+    };
+    Ok(Entry{
+      description: None,
+      prerelease: None,
+      build: None,
+      created_at: None,
+      updated_at: None,
+      deleted_at: None,
+      ..entry
+    }.rendered())
+  }
+}
+
+// A change to the template used to format the version of a series. The
+// template is removed if none is provided.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Formatting {
+  pub template: Option<String>,
+}
+
+impl Formatting {
+  pub fn validate(&self) -> Result<(), store::error::Error> {
+    if let Some(source) = &self.template {
+      template::Template::parse(source)?;
+    }
+    Ok(())
+  }
+}
+
+// Parameters that control how a series is deleted. Series are archived
+// unless they are purged, which deletes them permanently. The branches of
+// the series may be deleted along with it.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Deletion {
+  #[serde(default)]
+  pub purge: bool,
+  #[serde(default)]
+  pub branches: bool,
+}
+
+// Parameters that filter and page a listing of series.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Filter {
+  pub prefix: Option<String>,
+  pub archived: Option<bool>,
+  pub cursor: Option<String>,
+  pub limit: Option<usize>,
+}
+
+// Parameters that filter and page the version history of a series. The
+// time range is half-open, [since, until); the value range is inclusive.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct VersionFilter {
+  pub since: Option<chrono::DateTime<chrono::Utc>>,
+  pub until: Option<chrono::DateTime<chrono::Utc>>,
+  pub min: Option<i64>,
+  pub max: Option<i64>,
+  pub cursor: Option<String>,
+  pub limit: Option<usize>,
+}
+
+impl VersionFilter {
+  // Versions are ordered by value, then token, since pre-releases share
+  // the value of the version they lead up to. The cursor encodes both.
+  pub fn cursor(entry: &Entry) -> String {
+    format!("{}:{}", entry.value, entry.token.as_deref().unwrap_or(""))
+  }
+  
+  pub fn parse_cursor(&self) -> Result<Option<(i64, String)>, store::error::Error> {
+    let cursor = match &self.cursor {
+      Some(cursor) => cursor,
+      None => return Ok(None),
+    };
+    let invalid = || store::error::Error::InvalidOperationError(format!("Invalid cursor: {:?}", cursor));
+    let (value, token) = match cursor.split_once(':') {
+      Some(parts) => parts,
+      None => return Err(invalid()),
+    };
+    match value.parse::<i64>() {
+      Ok(value) => Ok(Some((value, token.to_string()))),
+      Err(_) => Err(invalid()),
+    }
+  }
+}
+
+// A pre-release label, the channel that produced it and the number of
+// the pre-release within that channel, e.g., "rc.3".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prerelease {
+  pub channel: String,
+  pub number: i64,
+}
+
+impl Prerelease {
+  pub fn new(channel: &str, number: i64) -> Prerelease {
+    Prerelease{
+      channel: channel.to_string(),
+      number: number,
+    }
+  }
+  
+  // Channel names must be usable as a semantic version pre-release
+  // identifier; that is, non-empty and limited to [0-9A-Za-z-].
+  pub fn validate_channel(channel: &str) -> Result<(), store::error::Error> {
+    if channel.len() == 0 || !channel.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+      return Err(store::error::Error::InvalidOperationError(format!("Invalid pre-release channel: {:?}", channel)));
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for Prerelease {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}", self.channel, self.number)
+  }
+}
+
+// Determine whether a key may be used for a new series. The key of a branch
+// is the key of its parent and the name of the branch separated by '/', so
+// the keys of series which are not branches may not contain '/'.
+pub fn validate_key(key: &str) -> Result<(), store::error::Error> {
+  if key.len() < 1 {
+    return Err(store::error::Error::InvalidOperationError("Series key must not be empty".to_string()));
+  }
+  if key.contains('/') {
+    return Err(store::error::Error::InvalidOperationError(format!("Series key must not contain '/': {:?}", key)));
+  }
+  Ok(())
+}
+
+// Path segments which name a sub-resource of a series. A token with one of
+// these names could not be addressed as /series/{key}/{token}, so they cannot
+// be used as tokens; the token routes also decline them, so that a rejected
+// request to a sub-resource does not fall through to the token route.
+pub const RESERVED_TOKENS: &[&str] = &["aliases", "attrs", "audit", "branches", "move", "restore", "schema", "template", "values", "versions"];
+
+// Determine whether a token may be used to advance a series.
+pub fn validate_token(token: &str) -> Result<(), store::error::Error> {
+  if RESERVED_TOKENS.contains(&token) {
+    return Err(store::error::Error::InvalidOperationError(format!("Token is reserved: {:?}", token)));
+  }
+  Ok(())
+}
+
+// Parameters that qualify an increment request. The expected value and
+// token, when provided, are preconditions on the current state of the
+// series which must hold for the increment to proceed.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Increment {
+  pub bump: Option<semver::Bump>,
+  pub channel: Option<String>,
+  pub build: Option<String>,
+  pub expect_value: Option<i64>,
+  pub expect_token: Option<String>,
+}
+
+impl Increment {
+  pub fn check_precondition(&self, entry: &Entry) -> Result<(), store::error::Error> {
+    if let Some(expect) = self.expect_value {
+      if expect != entry.value {
+        return Err(store::error::Error::PreconditionFailedError(format!("Series {:?} has value {}, expected {}", entry.key, entry.value, expect)));
+      }
+    }
+    if let Some(expect) = &self.expect_token {
+      if Some(expect) != entry.token.as_ref() {
+        return Err(store::error::Error::PreconditionFailedError(format!("Series {:?} has token {:?}, expected {:?}", entry.key, entry.token, expect)));
+      }
+    }
+    Ok(())
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Entry {
   pub key: String,
   pub creator_id: i64,
   pub token: Option<String>,
   pub value: i64,
+  pub kind: Kind,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub semver: Option<semver::Semver>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub calver: Option<calver::Calver>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub version: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub prerelease: Option<Prerelease>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub build: Option<String>,
+  pub step: i64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub template: Option<template::Template>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl Entry {
@@ -21,39 +444,217 @@ impl Entry {
       creator_id: creator_id,
       token: token,
       value: value,
+      kind: Kind::Counter,
+      semver: None,
+      calver: None,
+      version: None,
+      prerelease: None,
+      build: None,
+      step: 1,
+      max: None,
+      description: None,
+      template: None,
+      created_at: None,
+      updated_at: None,
+      deleted_at: None,
     }
   }
   
+  pub fn new_semver(key: &str, creator_id: i64, token: Option<String>, value: i64, semver: semver::Semver) -> Entry {
+    Entry{
+      kind: Kind::Semver,
+      semver: Some(semver),
+      ..Self::new(key, creator_id, token, value)
+    }
+  }
+  
+  pub fn new_calver(key: &str, creator_id: i64, token: Option<String>, value: i64, calver: calver::Calver) -> Entry {
+    Entry{
+      kind: Kind::Calver,
+      calver: Some(calver),
+      ..Self::new(key, creator_id, token, value)
+    }.rendered()
+  }
+  
+  // Columns are looked up by name, since series records are selected both
+  // directly and joined with their version history.
   pub fn unmarshal(row: &tokio_postgres::Row) -> Result<Entry, store::error::Error> {
+    let kind: String = row.try_get("kind")?;
+    let period: Option<String> = row.try_get("period")?;
+    let calver = match period {
+      Some(period) => Some(calver::Calver{
+        period: calver::Period::parse(&period)?,
+        utc_offset: row.try_get::<_, Option<i32>>("utc_offset")?.unwrap_or(0),
+        timezone: match row.try_get::<_, Option<String>>("timezone")? {
+          Some(name) => match name.parse() {
+            Ok(timezone) => Some(timezone),
+            Err(_) => return Err(store::error::Error::MarshalError),
+          },
+          None => None,
+        },
+        start: row.try_get("period_start")?,
+        number: row.try_get::<_, Option<i64>>("period_value")?.unwrap_or(0),
+      }),
+      None => None,
+    };
     Ok(Entry{
-      key: row.try_get(0)?,
-      creator_id: row.try_get(1)?,
-      token: row.try_get(2)?,
-      value: row.try_get(3)?,
-    })
+      key: row.try_get("key")?,
+      creator_id: row.try_get("creator_id")?,
+      token: row.try_get("token")?,
+      value: row.try_get("value")?,
+      kind: Kind::parse(&kind)?,
+      semver: semver::Semver::from_parts(row.try_get("major")?, row.try_get("minor")?, row.try_get("patch")?),
+      calver: calver,
+      version: None,
+      prerelease: None,
+      build: None,
+      step: row.try_get("step")?,
+      max: row.try_get("max")?,
+      description: row.try_get("description")?,
+      template: match row.try_get::<_, Option<String>>("template")? {
+        Some(source) => match template::Template::parse(&source) {
+          Ok(template) => Some(template),
+          Err(_) => return Err(store::error::Error::MarshalError),
+        },
+        None => None,
+      },
+      created_at: row.try_get("created_at")?,
+      updated_at: row.try_get("updated_at")?,
+      deleted_at: row.try_get("deleted_at")?,
+    }.rendered())
   }
   
-  pub fn next(&self) -> Entry {
+  // Unmarshal a version record, which carries the pre-release and build
+  // labels that produced it in addition to the series state.
+  pub fn unmarshal_version(row: &tokio_postgres::Row) -> Result<Entry, store::error::Error> {
+    let channel: Option<String> = row.try_get("channel")?;
+    let number: Option<i64> = row.try_get("channel_value")?;
+    Ok(Entry{
+      prerelease: match (channel, number) {
+        (Some(channel), Some(number)) => Some(Prerelease::new(&channel, number)),
+        _ => None,
+      },
+      build: row.try_get("build")?,
+      ..Self::unmarshal(row)?
+    }.rendered())
+  }
+  
+  // Determine whether the entry can be advanced without exceeding the upper
+  // bound of the series or overflowing its value.
+  pub fn check_next(&self) -> Result<(), store::error::Error> {
+    match self.value.checked_add(self.step) {
+      Some(v) => match self.max {
+        Some(max) if v > max => Err(store::error::Error::LimitExceededError(format!("Series {:?} cannot exceed {}", self.key, max))),
+        _ => Ok(()),
+      },
+      None => Err(store::error::Error::LimitExceededError(format!("Series {:?} cannot be advanced without overflowing", self.key))),
+    }
+  }
+  
+  fn advance(&self, token: Option<String>, bump: semver::Bump) -> Entry {
     Entry{
       key: self.key.to_owned(),
       creator_id: self.creator_id,
-      token: if let Some(tok) = &self.token { Some(tok.to_string()) } else { None },
-      value: self.value + 1,
-    }
+      token: token,
+      value: self.value.saturating_add(self.step),
+      kind: self.kind,
+      semver: self.semver.map(|v| v.bump(bump)),
+      calver: self.calver.clone(),
+      version: self.version.clone(),
+      prerelease: None,
+      build: None,
+      step: self.step,
+      max: self.max,
+      description: self.description.clone(),
+      template: self.template.clone(),
+      created_at: self.created_at,
+      updated_at: None,
+      deleted_at: None,
+    }.rendered()
   }
   
+  // Advance the entry by one step, as an unqualified increment does. These
+  // remain for the tests; the store always bumps an explicit component.
+  #[cfg(test)]
+  pub fn next(&self) -> Entry {
+    self.bump(semver::Bump::Patch)
+  }
+  
+  #[cfg(test)]
   pub fn next_with_token(&self, token: &str) -> Option<Entry> {
+    self.bump_with_token(token, semver::Bump::Patch)
+  }
+  
+  // Advance the entry, bumping the specified semantic version component.
+  // The bump has no effect on the version components of a counter series,
+  // which only carry the plain value.
+  pub fn bump(&self, part: semver::Bump) -> Entry {
+    self.advance(self.token.clone(), part)
+  }
+  
+  pub fn bump_with_token(&self, token: &str, part: semver::Bump) -> Option<Entry> {
     if let Some(tok) = &self.token {
       if tok == token {
         return None; // token matches, no update
       }
     }
-    Some(Entry{
-      key: self.key.to_owned(),
-      creator_id: self.creator_id,
-      token: Some(token.to_string()),
-      value: self.value + 1,
-    })
+    Some(self.advance(Some(token.to_string()), part))
+  }
+  
+  // Produce the pre-release of the next version in the specified channel.
+  // The entry itself is not advanced; a pre-release only leads up to the
+  // version that will be produced when it graduates.
+  pub fn prerelease(&self, token: &str, part: semver::Bump, channel: &str, number: i64) -> Entry {
+    Entry{
+      prerelease: Some(Prerelease::new(channel, number)),
+      ..self.advance(Some(token.to_string()), part)
+    }.rendered()
+  }
+  
+  // Advance the calendar counter of a CalVer series at the provided instant.
+  // Other kinds of series are unaffected.
+  pub fn with_calendar(&self, now: chrono::DateTime<chrono::Utc>) -> Entry {
+    match &self.calver {
+      Some(calver) => {
+        let calver = calver.advance(now);
+        Entry{
+          calver: Some(calver),
+          ..self.clone()
+        }.rendered()
+      },
+      None => self.clone(),
+    }
+  }
+  
+  pub fn with_timestamps(&self, created_at: chrono::DateTime<chrono::Utc>, updated_at: chrono::DateTime<chrono::Utc>) -> Entry {
+    Entry{
+      created_at: Some(created_at),
+      updated_at: Some(updated_at),
+      ..self.clone()
+    }
+  }
+  
+  pub fn with_build(&self, build: Option<String>) -> Entry {
+    Entry{
+      build: build,
+      ..self.clone()
+    }.rendered()
+  }
+  
+  // The formatted version of the entry. Series with a template are rendered
+  // with it; otherwise only CalVer series have a formatted version.
+  pub fn render(&self) -> Option<String> {
+    match &self.template {
+      Some(template) => Some(template.render(self)),
+      None => self.calver.as_ref().and_then(|c| c.version()),
+    }
+  }
+  
+  fn rendered(self) -> Entry {
+    Entry{
+      version: self.render(),
+      ..self
+    }
   }
   
 }
@@ -96,4 +697,197 @@ mod tests {
     assert_eq!(None, ent.next_with_token("3096048bb3")); // same again
   }
   
+  #[test]
+  fn bump_with_token() {
+    let ent = Entry::new_semver("a", 0, None, 0, semver::Semver::new(0, 0, 0));
+    let ent = ent.bump_with_token("d261470109", semver::Bump::Minor).unwrap();
+    assert_eq!(Entry::new_semver("a", 0, Some("d261470109".to_string()), 1, semver::Semver::new(0, 1, 0)), ent);
+    assert_eq!(None, ent.bump_with_token("d261470109", semver::Bump::Major)); // no change, same token
+    
+    let ent = ent.bump_with_token("3096048bb3", semver::Bump::Patch).unwrap();
+    assert_eq!(Entry::new_semver("a", 0, Some("3096048bb3".to_string()), 2, semver::Semver::new(0, 1, 1)), ent);
+    
+    let ent = ent.bump_with_token("8f1e2c0a77", semver::Bump::Major).unwrap();
+    assert_eq!(Entry::new_semver("a", 0, Some("8f1e2c0a77".to_string()), 3, semver::Semver::new(1, 0, 0)), ent);
+    
+    let ent = Entry::new("a", 0, None, 10); // counter series ignore components
+    assert_eq!(Some(Entry::new("a", 0, Some("d261470109".to_string()), 11)), ent.bump_with_token("d261470109", semver::Bump::Major));
+  }
+  
+  #[test]
+  fn prerelease() {
+    let ent = Entry::new_semver("a", 0, Some("d261470109".to_string()), 4, semver::Semver::new(1, 2, 3));
+    let pre = ent.prerelease("3096048bb3", semver::Bump::Minor, "rc", 2);
+    assert_eq!(Some(semver::Semver::new(1, 3, 0)), pre.semver);
+    assert_eq!(Some(Prerelease::new("rc", 2)), pre.prerelease);
+    assert_eq!(Some("3096048bb3".to_string()), pre.token);
+    assert_eq!(5, pre.value);
+    assert_eq!("rc.2", pre.prerelease.unwrap().to_string());
+  }
+  
+  #[test]
+  fn validate_channel() {
+    assert!(Prerelease::validate_channel("rc").is_ok());
+    assert!(Prerelease::validate_channel("beta-2").is_ok());
+    assert!(Prerelease::validate_channel("").is_err());
+    assert!(Prerelease::validate_channel("rc.1").is_err());
+    assert!(Prerelease::validate_channel("rc/1").is_err());
+  }
+  
+  #[test]
+  fn check_next() {
+    let ent = Entry{max: Some(12), ..Entry::new("a", 0, None, 10)};
+    assert!(ent.check_next().is_ok());
+    let ent = ent.next();
+    assert!(ent.check_next().is_ok());
+    let ent = ent.next();
+    assert!(ent.check_next().is_err());
+    
+    let ent = Entry{step: 5, ..Entry::new("a", 0, None, 10)};
+    assert_eq!(15, ent.next().value);
+    assert!(Entry::new("a", 0, None, i64::MAX).check_next().is_err());
+  }
+  
+  #[test]
+  fn create() {
+    let create = Create{key: "a".to_string(), kind: Kind::Counter, start: Some(4000), step: None, max: None, semver: None, period: None, utc_offset: None, timezone: None, description: None, template: None};
+    let ent = create.entry(0).unwrap();
+    assert_eq!(3999, ent.value);
+    assert_eq!(4000, ent.next().value);
+    
+    let create = Create{key: "a".to_string(), kind: Kind::Counter, start: None, step: Some(10), max: Some(100), semver: None, period: None, utc_offset: None, timezone: None, description: None, template: None};
+    let ent = create.entry(0).unwrap();
+    assert_eq!(10, ent.next().value);
+    
+    let create = Create{key: "a".to_string(), kind: Kind::Counter, start: Some(10), step: Some(0), max: None, semver: None, period: None, utc_offset: None, timezone: None, description: None, template: None};
+    assert!(create.entry(0).is_err());
+    let create = Create{key: "a".to_string(), kind: Kind::Counter, start: Some(10), step: None, max: Some(5), semver: None, period: None, utc_offset: None, timezone: None, description: None, template: None};
+    assert!(create.entry(0).is_err());
+  }
+  
+  #[test]
+  fn version_cursor() {
+    let ent = Entry::new("a", 0, Some("d261470109".to_string()), 12);
+    let cursor = VersionFilter::cursor(&ent);
+    assert_eq!("12:d261470109", cursor);
+    
+    let filter = VersionFilter{cursor: Some(cursor), ..Default::default()};
+    assert_eq!(Some((12, "d261470109".to_string())), filter.parse_cursor().unwrap());
+    let filter = VersionFilter{cursor: Some("12:a:b".to_string()), ..Default::default()};
+    assert_eq!(Some((12, "a:b".to_string())), filter.parse_cursor().unwrap());
+    let filter = VersionFilter{cursor: None, ..Default::default()};
+    assert_eq!(None, filter.parse_cursor().unwrap());
+    let filter = VersionFilter{cursor: Some("d261470109".to_string()), ..Default::default()};
+    assert!(filter.parse_cursor().is_err());
+  }
+  
+  #[test]
+  fn check_precondition() {
+    let ent = Entry::new("a", 0, Some("d261470109".to_string()), 12);
+    assert!(Increment::default().check_precondition(&ent).is_ok());
+    assert!(Increment{expect_value: Some(12), ..Default::default()}.check_precondition(&ent).is_ok());
+    assert!(Increment{expect_value: Some(11), ..Default::default()}.check_precondition(&ent).is_err());
+    assert!(Increment{expect_token: Some("d261470109".to_string()), ..Default::default()}.check_precondition(&ent).is_ok());
+    assert!(Increment{expect_token: Some("3096048bb3".to_string()), ..Default::default()}.check_precondition(&ent).is_err());
+    assert!(Increment{expect_value: Some(12), expect_token: Some("3096048bb3".to_string()), ..Default::default()}.check_precondition(&ent).is_err());
+    assert!(Increment{expect_token: Some("d261470109".to_string()), ..Default::default()}.check_precondition(&Entry::new("a", 0, None, 0)).is_err());
+  }
+  
+  #[test]
+  fn assignment() {
+    let ent = Entry{max: Some(100), ..Entry::new("a", 0, Some("d261470109".to_string()), 12)};
+    let set = Assignment{value: 20, semver: None, force: false, reason: None};
+    assert_eq!(Entry{max: Some(100), ..Entry::new("a", 0, None, 20)}, set.apply(&ent).unwrap());
+    
+    let set = Assignment{value: 10, semver: None, force: false, reason: None};
+    assert!(set.apply(&ent).is_err());
+    let set = Assignment{value: 10, semver: None, force: true, reason: None};
+    assert_eq!(10, set.apply(&ent).unwrap().value);
+    let set = Assignment{value: 101, semver: None, force: true, reason: None};
+    assert!(set.apply(&ent).is_err());
+    let set = Assignment{value: 20, semver: Some(semver::Semver::new(1, 0, 0)), force: false, reason: None};
+    assert!(set.apply(&ent).is_err()); // not a semver series
+    
+    let ent = Entry::new_semver("a", 0, None, 3, semver::Semver::new(1, 2, 0));
+    let set = Assignment{value: 4, semver: Some(semver::Semver::new(1, 1, 9)), force: false, reason: None};
+    assert!(set.apply(&ent).is_err());
+    let set = Assignment{value: 4, semver: Some(semver::Semver::new(2, 0, 0)), force: false, reason: None};
+    assert_eq!(Some(semver::Semver::new(2, 0, 0)), set.apply(&ent).unwrap().semver);
+    let set = Assignment{value: 4, semver: None, force: false, reason: None};
+    assert_eq!(Some(semver::Semver::new(1, 2, 0)), set.apply(&ent).unwrap().semver);
+  }
+  
+  #[test]
+  fn move_target() {
+    let mv = Move{key: Some("b".to_string()), account_id: None};
+    assert_eq!((0, "b".to_string()), mv.target(0, "a").unwrap());
+    let mv = Move{key: None, account_id: Some(1)};
+    assert_eq!((1, "a".to_string()), mv.target(0, "a").unwrap());
+    let mv = Move{key: Some("b".to_string()), account_id: Some(1)};
+    assert_eq!((1, "b".to_string()), mv.target(0, "a").unwrap());
+    assert!(Move::default().target(0, "a").is_err());
+    assert!(Move{key: Some("".to_string()), account_id: None}.target(0, "a").is_err());
+    assert!(Move{key: Some("a/b".to_string()), account_id: None}.target(0, "a").is_err());
+  }
+  
+  #[test]
+  fn with_calendar() {
+    use chrono::TimeZone;
+    let now = chrono::Utc.ymd(2022, 5, 4).and_hms(12, 0, 0);
+    let ent = Entry::new_calver("a", 0, None, 0, calver::Calver::new(calver::Period::Month, 0).unwrap());
+    let ent = ent.next_with_token("1").unwrap().with_calendar(now);
+    assert_eq!(1, ent.value);
+    assert_eq!(Some("2022.05.1".to_string()), ent.version);
+    let ent = ent.next_with_token("2").unwrap().with_calendar(now);
+    assert_eq!(2, ent.value);
+    assert_eq!(Some("2022.05.2".to_string()), ent.version);
+    assert_eq!(None, ent.next_with_token("2"));
+    
+    let ent = Entry::new("a", 0, None, 0);
+    assert_eq!(ent, ent.with_calendar(now));
+  }
+  
+  #[test]
+  fn branch() {
+    let parent = Entry{step: 10, max: Some(1000), description: Some("App".to_string()), ..Entry::new("app", 0, Some("abc".to_string()), 120)};
+    let branch = Branch{name: "main".to_string(), seed: false}.entry(&parent).unwrap();
+    assert_eq!(Entry{step: 10, max: Some(1000), ..Entry::new("app/main", 0, None, 0)}, branch);
+    let branch = Branch{name: "main".to_string(), seed: true}.entry(&parent).unwrap();
+    assert_eq!(Entry{step: 10, max: Some(1000), ..Entry::new("app/main", 0, None, 120)}, branch);
+    
+    let parent = Entry::new_semver("app", 0, None, 3, semver::Semver::new(1, 2, 0));
+    let branch = Branch{name: "main".to_string(), seed: false}.entry(&parent).unwrap();
+    assert_eq!(Some(semver::Semver::new(0, 0, 0)), branch.semver);
+    let branch = Branch{name: "main".to_string(), seed: true}.entry(&parent).unwrap();
+    assert_eq!(Some(semver::Semver::new(1, 2, 0)), branch.semver);
+    
+    assert!(Branch{name: "".to_string(), seed: false}.entry(&parent).is_err());
+    assert!(Branch{name: "a/b".to_string(), seed: false}.entry(&parent).is_err());
+    assert!(Branch::validate_name("release-1.2_x").is_ok());
+  }
+  
+  #[test]
+  fn series_keys() {
+    assert!(validate_key("app").is_ok());
+    assert!(validate_key("").is_err());
+    assert!(validate_key("app/main").is_err());
+  }
+  
+  #[test]
+  fn reserved_tokens() {
+    assert!(validate_token("d261470109").is_ok());
+    assert!(validate_token("attrs").is_err());
+    assert!(validate_token("versions").is_err());
+  }
+  
+  #[test]
+  fn render() {
+    let ent = Entry{template: Some(template::Template::parse("v{value}-{token:3}").unwrap()), ..Entry::new("a", 0, None, 0)};
+    let ent = ent.next_with_token("abcdef").unwrap();
+    assert_eq!(Some("v1-abc".to_string()), ent.version);
+    let ent = ent.with_build(Some("x".to_string()));
+    assert_eq!(Some("v1-abc".to_string()), ent.version);
+    assert_eq!(None, Entry::new("a", 0, None, 0).render());
+  }
+  
 }
@@ -0,0 +1,148 @@
+use std::collections;
+
+use crate::store;
+use crate::model::attrs;
+
+use warp;
+use serde::{Serialize, Deserialize};
+use serde_json::{self, json};
+use tokio_postgres;
+
+pub const DEFAULT_MAX_VALUE_BYTES: i64 = 64 * 1024;
+pub const DEFAULT_MAX_ATTRS_PER_TOKEN: i64 = 256;
+pub const DEFAULT_MAX_ACCOUNT_BYTES: i64 = 256 * 1024 * 1024;
+pub const DEFAULT_MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+// Limits on the token attributes stored in an account. Sizes are measured
+// in bytes of the stored text of a value, which is the JSON representation
+// of values that are not strings; the usage of an account also counts the
+// names of its attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+  pub max_value_bytes: i64,
+  pub max_attrs_per_token: i64,
+  pub max_account_bytes: i64,
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Limits{
+      max_value_bytes: DEFAULT_MAX_VALUE_BYTES,
+      max_attrs_per_token: DEFAULT_MAX_ATTRS_PER_TOKEN,
+      max_account_bytes: DEFAULT_MAX_ACCOUNT_BYTES,
+    }
+  }
+}
+
+// Limits which apply to a specific account. Any limit which is not set
+// falls back to the limit of the deployment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Overrides {
+  pub max_value_bytes: Option<i64>,
+  pub max_attrs_per_token: Option<i64>,
+  pub max_account_bytes: Option<i64>,
+}
+
+impl Overrides {
+  pub fn unmarshal(row: &tokio_postgres::Row) -> Result<Overrides, store::error::Error> {
+    Ok(Overrides{
+      max_value_bytes: row.try_get("attr_max_value_bytes")?,
+      max_attrs_per_token: row.try_get("attr_max_per_token")?,
+      max_account_bytes: row.try_get("attr_max_account_bytes")?,
+    })
+  }
+
+  pub fn validate(&self) -> Result<(), store::error::Error> {
+    for (name, limit) in &[("max_value_bytes", self.max_value_bytes), ("max_attrs_per_token", self.max_attrs_per_token), ("max_account_bytes", self.max_account_bytes)] {
+      if let Some(limit) = limit {
+        if *limit < 1 {
+          return Err(store::error::Error::InvalidOperationError(format!("Limit must be positive: {}", name)));
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Limits {
+  pub fn with_overrides(&self, overrides: &Overrides) -> Limits {
+    Limits{
+      max_value_bytes: overrides.max_value_bytes.unwrap_or(self.max_value_bytes),
+      max_attrs_per_token: overrides.max_attrs_per_token.unwrap_or(self.max_attrs_per_token),
+      max_account_bytes: overrides.max_account_bytes.unwrap_or(self.max_account_bytes),
+    }
+  }
+
+  pub fn check_values(&self, attrs: &collections::HashMap<String, serde_json::Value>) -> Result<(), store::error::Error> {
+    for (name, value) in attrs {
+      if size(value) > self.max_value_bytes {
+        return Err(store::error::Error::PayloadTooLargeError(format!("Attribute {:?} exceeds {} bytes", name, self.max_value_bytes)));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn check_count(&self, count: usize) -> Result<(), store::error::Error> {
+    if count as i64 > self.max_attrs_per_token {
+      Err(store::error::Error::PayloadTooLargeError(format!("Tokens cannot have more than {} attributes", self.max_attrs_per_token)))
+    }else{
+      Ok(())
+    }
+  }
+
+  pub fn check_usage(&self, usage: i64) -> Result<(), store::error::Error> {
+    if usage > self.max_account_bytes {
+      Err(store::error::Error::PayloadTooLargeError(format!("Attributes in the account cannot exceed {} bytes", self.max_account_bytes)))
+    }else{
+      Ok(())
+    }
+  }
+}
+
+impl warp::Reply for Limits {
+  fn into_response(self) -> warp::reply::Response {
+    warp::reply::Response::new(json!(self).to_string().into())
+  }
+}
+
+// The number of bytes used to store an attribute value.
+pub fn size(value: &serde_json::Value) -> i64 {
+  attrs::text(value).len() as i64
+}
+
+// The number of bytes used to store a set of attributes, including their
+// names.
+pub fn total(attrs: &collections::HashMap<String, serde_json::Value>) -> i64 {
+  attrs.iter().map(|(name, value)| name.len() as i64 + size(value)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn attrs(v: serde_json::Value) -> collections::HashMap<String, serde_json::Value> {
+    serde_json::from_value(v).unwrap()
+  }
+
+  #[test]
+  fn overrides() {
+    let limits = Limits::default().with_overrides(&Overrides{max_value_bytes: Some(8), ..Default::default()});
+    assert_eq!(Limits{max_value_bytes: 8, ..Default::default()}, limits);
+    assert!(Overrides{max_account_bytes: Some(0), ..Default::default()}.validate().is_err());
+    assert!(Overrides::default().validate().is_ok());
+  }
+
+  #[test]
+  fn check() {
+    let limits = Limits{max_value_bytes: 4, max_attrs_per_token: 2, max_account_bytes: 16};
+    assert!(limits.check_values(&attrs(json!({"a": "abcd", "b": 1234, "c": [1]}))).is_ok());
+    assert!(limits.check_values(&attrs(json!({"a": "abcde"}))).is_err());
+    assert!(limits.check_values(&attrs(json!({"a": [1, 2]}))).is_err());
+    assert!(limits.check_count(2).is_ok());
+    assert!(limits.check_count(3).is_err());
+    assert!(limits.check_usage(16).is_ok());
+    assert!(limits.check_usage(17).is_err());
+    assert_eq!(11, total(&attrs(json!({"a": "abcd", "b": 12345}))));
+  }
+
+}
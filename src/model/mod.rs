@@ -1,4 +1,16 @@
 pub mod account;
 pub mod entry;
+pub mod semver;
+pub mod calver;
+pub mod template;
+pub mod output;
+pub mod page;
+pub mod version;
+pub mod series;
+pub mod query;
+pub mod audit;
+pub mod alias;
 pub mod attrs;
+pub mod schema;
+pub mod limits;
 pub mod apikey;
@@ -0,0 +1,205 @@
+use std::collections;
+
+use crate::store;
+use crate::model::entry;
+use crate::model::attrs;
+
+use warp::{self, http, Reply};
+use serde::Deserialize;
+use serde_json;
+
+const MEDIA_TYPE_JSON: &str = "application/json";
+const MEDIA_TYPE_TEXT: &str = "text/plain";
+const MEDIA_TYPE_ENV: &str = "text/x-dotenv";
+
+const ENV_PREFIX: &str = "MONOTRON_";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+  #[default]
+  Json,
+  Text,
+  Env,
+}
+
+impl Format {
+  fn from_media_type(media_type: &str) -> Option<Format> {
+    match media_type {
+      MEDIA_TYPE_JSON | "application/*" | "*/*" => Some(Format::Json),
+      MEDIA_TYPE_TEXT | "text/*" => Some(Format::Text),
+      MEDIA_TYPE_ENV => Some(Format::Env),
+      _ => None,
+    }
+  }
+
+  // Select the format preferred by an Accept header. Media ranges are
+  // considered in order of their quality, and in the order they were
+  // provided when their quality is the same. There is no preference when
+  // none of the media ranges are supported.
+  pub fn negotiate(accept: &str) -> Option<Format> {
+    let mut ranges: Vec<(f32, Format)> = Vec::new();
+    for range in accept.split(',') {
+      let mut params = range.split(';');
+      let media_type = match params.next() {
+        Some(v) => v.trim().to_lowercase(),
+        None => continue,
+      };
+      let mut quality: f32 = 1.0;
+      for param in params {
+        if let Some((name, value)) = param.split_once('=') {
+          if name.trim() == "q" {
+            quality = value.trim().parse().unwrap_or(0.0);
+          }
+        }
+      }
+      if quality <= 0.0 {
+        continue;
+      }
+      if let Some(format) = Format::from_media_type(&media_type) {
+        ranges.push((quality, format));
+      }
+    }
+    ranges.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    ranges.first().map(|r| r.1)
+  }
+
+  fn content_type(&self) -> &'static str {
+    match self {
+      Format::Json => MEDIA_TYPE_JSON,
+      Format::Text => "text/plain; charset=utf-8",
+      Format::Env => "text/x-dotenv; charset=utf-8",
+    }
+  }
+}
+
+// Parameters that control how a series is represented in a response. An
+// explicit format takes precedence over the Accept header.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Options {
+  pub format: Option<Format>,
+}
+
+impl Options {
+  pub fn negotiate(self, accept: Option<String>) -> Options {
+    match (self.format, accept) {
+      (None, Some(accept)) => Options{
+        format: Format::negotiate(&accept),
+      },
+      _ => self,
+    }
+  }
+
+  pub fn is_json(&self) -> bool {
+    self.format.unwrap_or_default() == Format::Json
+  }
+
+  // Determine whether the representation includes the attributes of the
+  // token that produced the entry.
+  pub fn wants_attrs(&self) -> bool {
+    self.format == Some(Format::Env)
+  }
+
+  // Produce a response describing the provided entry. The text format is
+  // just the formatted version of the entry, or its value if it has none,
+  // so that it can be written directly to a file. The env format can be
+  // sourced by a shell.
+  pub fn reply(&self, entry: entry::Entry, attrs: &collections::HashMap<String, serde_json::Value>) -> Result<warp::reply::Response, store::error::Error> {
+    let format = self.format.unwrap_or_default();
+    let text = match format {
+      Format::Json => return Ok(entry.into_response()),
+      Format::Text => match entry.version {
+        Some(version) => format!("{}\n", version),
+        None => format!("{}\n", entry.value),
+      },
+      Format::Env => env(&entry, attrs)?,
+    };
+    let mut res = warp::reply::Response::new(text.into());
+    res.headers_mut().insert(http::header::CONTENT_TYPE, http::HeaderValue::from_static(format.content_type()));
+    Ok(res)
+  }
+}
+
+// Format an entry and the attributes of its token as env-file assignments.
+// Attribute names are upper-cased and any characters that cannot appear in
+// a variable name are replaced with underscores. Since distinct names may
+// produce the same variable, e.g., "git.sha" and "git_sha", such attributes
+// cannot be represented and are rejected rather than one of them being
+// silently dropped. Attributes that are not strings are assigned their JSON
+// representation.
+fn env(entry: &entry::Entry, attrs: &collections::HashMap<String, serde_json::Value>) -> Result<String, store::error::Error> {
+  let mut vars: Vec<(String, String)> = vec![
+    ("KEY".to_string(), entry.key.to_owned()),
+    ("VALUE".to_string(), entry.value.to_string()),
+  ];
+  if let Some(token) = &entry.token {
+    vars.push(("TOKEN".to_string(), token.to_owned()));
+  }
+  if let Some(version) = &entry.version {
+    vars.push(("VERSION".to_string(), version.to_owned()));
+  }
+
+  let mut names: Vec<&String> = attrs.keys().collect();
+  names.sort();
+  let mut seen: collections::HashMap<String, &String> = collections::HashMap::new();
+  for name in names {
+    let var: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+    let var = format!("ATTR_{}", var);
+    if let Some(other) = seen.insert(var.to_owned(), name) {
+      return Err(store::error::Error::ConflictError(format!("Attributes {:?} and {:?} would both be assigned to {}{}", other, name, ENV_PREFIX, var)));
+    }
+    vars.push((var, attrs::text(&attrs[name])));
+  }
+
+  let mut res = String::new();
+  for (name, value) in vars {
+    res.push_str(&format!("{}{}={}\n", ENV_PREFIX, name, quote(&value)));
+  }
+  Ok(res)
+}
+
+// Quote a value so that it is interpreted literally by a shell.
+fn quote(value: &str) -> String {
+  if value.len() > 0 && value.chars().all(|c| c.is_ascii_alphanumeric() || "._-+:/@".contains(c)) {
+    value.to_string()
+  }else{
+    format!("'{}'", value.replace('\'', "'\\''"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negotiate() {
+    assert_eq!(Some(Format::Json), Format::negotiate("application/json"));
+    assert_eq!(Some(Format::Text), Format::negotiate("text/plain"));
+    assert_eq!(Some(Format::Env), Format::negotiate("text/x-dotenv"));
+    assert_eq!(Some(Format::Json), Format::negotiate("*/*"));
+    assert_eq!(Some(Format::Text), Format::negotiate("text/html, text/plain;charset=utf-8, */*;q=0.8"));
+    assert_eq!(Some(Format::Env), Format::negotiate("application/json;q=0.5, text/x-dotenv"));
+    assert_eq!(Some(Format::Json), Format::negotiate("text/plain;q=0, application/json"));
+    assert_eq!(None, Format::negotiate("text/html"));
+
+    let opts = Options{format: Some(Format::Json)};
+    assert_eq!(Some(Format::Json), opts.negotiate(Some("text/plain".to_string())).format);
+    let opts = Options{format: None};
+    assert_eq!(Some(Format::Text), opts.negotiate(Some("text/plain".to_string())).format);
+  }
+
+  #[test]
+  fn render_env() {
+    let ent = entry::Entry::new("a", 0, Some("d261470".to_string()), 42);
+    let mut attrs: collections::HashMap<String, serde_json::Value> = collections::HashMap::new();
+    attrs.insert("git.sha".to_string(), serde_json::json!("d261470"));
+    attrs.insert("message".to_string(), serde_json::json!("Don't panic"));
+    attrs.insert("size".to_string(), serde_json::json!(1024));
+    attrs.insert("tags".to_string(), serde_json::json!(["a", "b"]));
+    assert_eq!("MONOTRON_KEY=a\nMONOTRON_VALUE=42\nMONOTRON_TOKEN=d261470\nMONOTRON_ATTR_GIT_SHA=d261470\nMONOTRON_ATTR_MESSAGE='Don'\\''t panic'\nMONOTRON_ATTR_SIZE=1024\nMONOTRON_ATTR_TAGS='[\"a\",\"b\"]'\n", env(&ent, &attrs).unwrap());
+    
+    attrs.insert("Git-Sha".to_string(), serde_json::json!("d261470"));
+    assert!(matches!(env(&ent, &attrs), Err(store::error::Error::ConflictError(_))));
+  }
+
+}
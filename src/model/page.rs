@@ -0,0 +1,76 @@
+use warp;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 500;
+
+// Clamp a requested page size to the range we are willing to serve.
+pub fn limit(limit: Option<usize>) -> usize {
+  match limit {
+    Some(limit) if limit < 1 => 1,
+    Some(limit) if limit > MAX_LIMIT => MAX_LIMIT,
+    Some(limit) => limit,
+    None => DEFAULT_LIMIT,
+  }
+}
+
+// A page of results. When more results are available the cursor is set
+// and may be provided with the next request to continue from this page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page<T: Serialize> {
+  pub data: Vec<T>,
+  pub cursor: Option<String>,
+}
+
+impl<T: Serialize> Page<T> {
+  // Produce a page from a result set which was fetched with one more
+  // record than the page size, which tells us whether there is a next
+  // page. The cursor is derived from the last record in the page.
+  pub fn new<F>(mut data: Vec<T>, limit: usize, cursor: F) -> Page<T>
+  where
+    F: Fn(&T) -> String,
+  {
+    let more = data.len() > limit;
+    data.truncate(limit);
+    let cursor = if more {
+      data.last().map(cursor)
+    }else{
+      None
+    };
+    Page{
+      data: data,
+      cursor: cursor,
+    }
+  }
+}
+
+impl<T: Serialize + Send> warp::Reply for Page<T> {
+  fn into_response(self) -> warp::reply::Response {
+    warp::reply::Response::new(json!(self).to_string().into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn page_cursor() {
+    let p = Page::new(vec![1, 2, 3, 4], 3, |e| e.to_string());
+    assert_eq!(Page{data: vec![1, 2, 3], cursor: Some("3".to_string())}, p);
+    let p = Page::new(vec![1, 2, 3], 3, |e| e.to_string());
+    assert_eq!(Page{data: vec![1, 2, 3], cursor: None}, p);
+    let p = Page::new(Vec::<i64>::new(), 3, |e| e.to_string());
+    assert_eq!(Page{data: vec![], cursor: None}, p);
+  }
+
+  #[test]
+  fn page_limit() {
+    assert_eq!(DEFAULT_LIMIT, limit(None));
+    assert_eq!(1, limit(Some(0)));
+    assert_eq!(10, limit(Some(10)));
+    assert_eq!(MAX_LIMIT, limit(Some(MAX_LIMIT + 1)));
+  }
+
+}
@@ -0,0 +1,134 @@
+use crate::store;
+use crate::model::entry;
+use crate::model::attrs;
+
+use serde::{Serialize, Deserialize};
+use serde_json;
+use base64;
+
+pub const MAX_PREDICATES: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+  #[default]
+  Asc,
+  Desc,
+}
+
+// A predicate on a token attribute. Exactly one of the conditions must be
+// provided: the attribute is equal to a value, which must have the same
+// type; the attribute is a string which begins with a prefix; or the
+// attribute exists, or does not.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Predicate {
+  pub name: String,
+  pub eq: Option<serde_json::Value>,
+  pub prefix: Option<String>,
+  pub exists: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+  Equal(attrs::Kind, String),
+  Prefix(String),
+  Exists(bool),
+}
+
+impl Predicate {
+  pub fn condition(&self) -> Result<Condition, store::error::Error> {
+    if self.name.len() == 0 {
+      return Err(store::error::Error::InvalidOperationError("Attribute name is required".to_string()));
+    }
+    match (&self.eq, &self.prefix, self.exists) {
+      (Some(value), None, None) => {
+        let (kind, text) = attrs::encode(&self.name, value)?;
+        Ok(Condition::Equal(kind, text))
+      },
+      (None, Some(prefix), None) => Ok(Condition::Prefix(prefix.to_owned())),
+      (None, None, Some(exists)) => Ok(Condition::Exists(exists)),
+      _ => Err(store::error::Error::InvalidOperationError(format!("Exactly one of 'eq', 'prefix' or 'exists' is required: {:?}", self.name))),
+    }
+  }
+}
+
+// A query for the versions of a series, or of every series in an account,
+// whose token attributes match every predicate. Versions are ordered by
+// series, then value, then token.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Query {
+  #[serde(default)]
+  pub attrs: Vec<Predicate>,
+  pub order: Option<Order>,
+  pub cursor: Option<String>,
+  pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Cursor(String, i64, String);
+
+impl Query {
+  pub fn conditions(&self) -> Result<Vec<(String, Condition)>, store::error::Error> {
+    if self.attrs.len() > MAX_PREDICATES {
+      return Err(store::error::Error::InvalidOperationError(format!("Too many predicates; at most {} are allowed", MAX_PREDICATES)));
+    }
+    let mut res: Vec<(String, Condition)> = Vec::new();
+    for pred in &self.attrs {
+      res.push((pred.name.to_owned(), pred.condition()?));
+    }
+    Ok(res)
+  }
+
+  // The cursor encodes the series, value and token of a version, since
+  // results may span several series. It is opaque to clients.
+  pub fn cursor(entry: &entry::Entry) -> String {
+    let cursor = Cursor(entry.key.to_owned(), entry.value, entry.token.clone().unwrap_or_default());
+    base64::encode_config(serde_json::to_string(&cursor).unwrap_or_default(), base64::URL_SAFE_NO_PAD)
+  }
+
+  pub fn parse_cursor(&self) -> Result<Option<(String, i64, String)>, store::error::Error> {
+    let cursor = match &self.cursor {
+      Some(cursor) => cursor,
+      None => return Ok(None),
+    };
+    let invalid = || store::error::Error::InvalidOperationError(format!("Invalid cursor: {:?}", cursor));
+    let data = match base64::decode_config(cursor, base64::URL_SAFE_NO_PAD) {
+      Ok(data) => data,
+      Err(_) => return Err(invalid()),
+    };
+    match serde_json::from_slice::<Cursor>(&data) {
+      Ok(Cursor(key, value, token)) => Ok(Some((key, value, token))),
+      Err(_) => Err(invalid()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn conditions() {
+    let pred = |eq, prefix: Option<&str>, exists| Predicate{name: "a".to_string(), eq: eq, prefix: prefix.map(|p| p.to_string()), exists: exists};
+    assert_eq!(Condition::Equal(attrs::Kind::String, "main".to_string()), pred(Some(json!("main")), None, None).condition().unwrap());
+    assert_eq!(Condition::Equal(attrs::Kind::Boolean, "true".to_string()), pred(Some(json!(true)), None, None).condition().unwrap());
+    assert_eq!(Condition::Prefix("v1.".to_string()), pred(None, Some("v1."), None).condition().unwrap());
+    assert_eq!(Condition::Exists(false), pred(None, None, Some(false)).condition().unwrap());
+    assert!(pred(None, None, None).condition().is_err());
+    assert!(pred(Some(json!(1)), Some("1"), None).condition().is_err());
+
+    let query = Query{attrs: vec![pred(None, None, Some(true)); MAX_PREDICATES + 1], ..Default::default()};
+    assert!(query.conditions().is_err());
+  }
+
+  #[test]
+  fn cursor() {
+    let ent = entry::Entry::new("a/b:c", 0, Some("000001".to_string()), 42);
+    let query = Query{cursor: Some(Query::cursor(&ent)), ..Default::default()};
+    assert_eq!(Some(("a/b:c".to_string(), 42, "000001".to_string())), query.parse_cursor().unwrap());
+    let query = Query{cursor: Some("nope".to_string()), ..Default::default()};
+    assert!(query.parse_cursor().is_err());
+  }
+
+}
@@ -0,0 +1,229 @@
+use std::collections;
+
+use crate::store;
+use crate::model::attrs;
+
+use warp;
+use regex;
+use serde::{Serialize, Deserialize};
+use serde_json::{self, json};
+
+// Constraints on the value of a specific attribute. Patterns are matched
+// against the text of the value, which is the JSON representation of
+// values that are not strings; they are not anchored unless they say so.
+// Lengths are measured in characters.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub pattern: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_length: Option<usize>,
+}
+
+// The attributes that may be stored for the tokens of a series. When the
+// allowed names are provided no other attributes may be stored. Required
+// attributes must be present once any attribute has been stored for a
+// token.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub allowed: Option<Vec<String>>,
+  #[serde(default)]
+  pub required: Vec<String>,
+  #[serde(default)]
+  pub attrs: collections::BTreeMap<String, Rule>,
+  #[serde(skip)]
+  patterns: collections::BTreeMap<String, Pattern>,
+}
+
+// A compiled attribute pattern. Patterns are equal when their sources are.
+#[derive(Debug, Clone)]
+struct Pattern(regex::Regex);
+
+impl PartialEq for Pattern {
+  fn eq(&self, other: &Pattern) -> bool {
+    self.0.as_str() == other.0.as_str()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+  NotAllowed, Required, Pattern, MaxLength,
+}
+
+// A reason an attribute does not conform to a schema.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Violation {
+  pub name: String,
+  pub reason: Reason,
+  pub message: String,
+}
+
+impl Violation {
+  fn new(name: &str, reason: Reason, message: String) -> Violation {
+    Violation{
+      name: name.to_string(),
+      reason: reason,
+      message: message,
+    }
+  }
+}
+
+impl Schema {
+  // Parse a stored schema. Stored schemas have been compiled before, so one
+  // which cannot be is malformed.
+  pub fn parse(text: &str) -> Result<Schema, store::error::Error> {
+    let schema: Schema = match serde_json::from_str(text) {
+      Ok(schema) => schema,
+      Err(_) => return Err(store::error::Error::MarshalError),
+    };
+    match schema.compile() {
+      Ok(schema) => Ok(schema),
+      Err(_) => Err(store::error::Error::MarshalError),
+    }
+  }
+
+  // Validate the schema and compile its patterns, producing the schema which
+  // attributes are checked against.
+  pub fn compile(&self) -> Result<Schema, store::error::Error> {
+    if let Some(allowed) = &self.allowed {
+      for name in self.required.iter().chain(self.attrs.keys()) {
+        if !allowed.contains(name) {
+          return Err(store::error::Error::InvalidOperationError(format!("Attribute is not allowed by the schema: {:?}", name)));
+        }
+      }
+    }
+    let mut patterns = collections::BTreeMap::new();
+    for (name, rule) in &self.attrs {
+      if let Some(pattern) = &rule.pattern {
+        match regex::Regex::new(pattern) {
+          Ok(re) => patterns.insert(name.to_owned(), Pattern(re)),
+          Err(err) => return Err(store::error::Error::InvalidOperationError(format!("Invalid pattern for attribute {:?}: {}", name, err))),
+        };
+      }
+      if rule.max_length == Some(0) {
+        return Err(store::error::Error::InvalidOperationError(format!("Invalid maximum length for attribute {:?}", name)));
+      }
+    }
+    Ok(Schema{
+      patterns: patterns,
+      ..self.clone()
+    })
+  }
+
+  // Check the attributes being stored for a token, which are merged with
+  // the attributes it already has, against the schema. Every violation is
+  // reported, ordered by attribute name.
+  pub fn check(&self, attrs: &collections::HashMap<String, serde_json::Value>, existing: &collections::HashMap<String, serde_json::Value>) -> Result<(), store::error::Error> {
+    let mut violations: Vec<Violation> = Vec::new();
+
+    let mut names: Vec<&String> = attrs.keys().collect();
+    names.sort();
+    for name in names {
+      if let Some(allowed) = &self.allowed {
+        if !allowed.contains(name) {
+          violations.push(Violation::new(name, Reason::NotAllowed, format!("Attribute is not allowed: {:?}", name)));
+          continue;
+        }
+      }
+      let rule = match self.attrs.get(name) {
+        Some(rule) => rule,
+        None => continue,
+      };
+      let text = attrs::text(&attrs[name]);
+      if let Some(Pattern(re)) = self.patterns.get(name) {
+        if !re.is_match(&text) {
+          violations.push(Violation::new(name, Reason::Pattern, format!("Attribute does not match pattern: {:?}", re.as_str())));
+        }
+      }
+      if let Some(max) = rule.max_length {
+        if text.chars().count() > max {
+          violations.push(Violation::new(name, Reason::MaxLength, format!("Attribute exceeds {} characters", max)));
+        }
+      }
+    }
+
+    let mut required: Vec<&String> = self.required.iter().collect();
+    required.sort();
+    for name in required {
+      if !attrs.contains_key(name) && !existing.contains_key(name) {
+        violations.push(Violation::new(name, Reason::Required, format!("Attribute is required: {:?}", name)));
+      }
+    }
+
+    if violations.len() > 0 {
+      Err(store::error::Error::ValidationError(violations))
+    }else{
+      Ok(())
+    }
+  }
+}
+
+impl warp::Reply for Schema {
+  fn into_response(self) -> warp::reply::Response {
+    warp::reply::Response::new(json!(self).to_string().into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn attrs(v: serde_json::Value) -> collections::HashMap<String, serde_json::Value> {
+    serde_json::from_value(v).unwrap()
+  }
+
+  fn violations(res: Result<(), store::error::Error>) -> Vec<(String, Reason)> {
+    match res {
+      Err(store::error::Error::ValidationError(v)) => v.into_iter().map(|v| (v.name, v.reason)).collect(),
+      Err(err) => panic!("Unexpected error: {}", err),
+      Ok(_) => vec![],
+    }
+  }
+
+  #[test]
+  fn check() {
+    let schema: Schema = serde_json::from_value(json!({
+      "allowed": ["commit_sha", "branch", "released"],
+      "required": ["commit_sha"],
+      "attrs": {
+        "commit_sha": {"pattern": "^[0-9a-f]+$", "max_length": 8},
+        "released": {"pattern": "^(true|false)$"}
+      }
+    })).unwrap();
+    let schema = schema.compile().unwrap();
+
+    let none = attrs(json!({}));
+    assert_eq!(Vec::<(String, Reason)>::new(), violations(schema.check(&attrs(json!({"commit_sha": "d261470", "released": true})), &none)));
+    assert_eq!(Vec::<(String, Reason)>::new(), violations(schema.check(&attrs(json!({"branch": "main"})), &attrs(json!({"commit_sha": "d261470"})))));
+    assert_eq!(vec![
+      ("commitSha".to_string(), Reason::NotAllowed),
+      ("commit_sha".to_string(), Reason::Required),
+    ], violations(schema.check(&attrs(json!({"commitSha": "d261470"})), &none)));
+    assert_eq!(vec![
+      ("commit_sha".to_string(), Reason::Pattern),
+      ("commit_sha".to_string(), Reason::MaxLength),
+      ("released".to_string(), Reason::Pattern),
+    ], violations(schema.check(&attrs(json!({"commit_sha": "not-a-sha", "released": "yes"})), &none)));
+  }
+
+  #[test]
+  fn parse() {
+    let schema = Schema::parse(r#"{"attrs": {"a": {"pattern": "^[0-9]+$"}}}"#).unwrap();
+    assert_eq!(vec![("a".to_string(), Reason::Pattern)], violations(schema.check(&attrs(json!({"a": "x"})), &attrs(json!({})))));
+    assert!(Schema::parse(r#"{"attrs": {"a": {"pattern": "("}}}"#).is_err());
+  }
+
+  #[test]
+  fn compile() {
+    assert!(Schema::default().compile().is_ok());
+    let schema: Schema = serde_json::from_value(json!({"allowed": ["a"], "required": ["b"]})).unwrap();
+    assert!(schema.compile().is_err());
+    let schema: Schema = serde_json::from_value(json!({"attrs": {"a": {"pattern": "("}}})).unwrap();
+    assert!(schema.compile().is_err());
+    let schema: Schema = serde_json::from_value(json!({"attrs": {"a": {"max_length": 0}}})).unwrap();
+    assert!(schema.compile().is_err());
+  }
+
+}
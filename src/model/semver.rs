@@ -0,0 +1,90 @@
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bump {
+  Major, Minor, Patch,
+}
+
+impl fmt::Display for Bump {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Bump::Major => write!(f, "major"),
+      Bump::Minor => write!(f, "minor"),
+      Bump::Patch => write!(f, "patch"),
+    }
+  }
+}
+
+// Components are declared in order of precedence, so the derived ordering
+// is the semantic version ordering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Semver {
+  pub major: i64,
+  pub minor: i64,
+  pub patch: i64,
+}
+
+impl Semver {
+  pub fn new(major: i64, minor: i64, patch: i64) -> Semver {
+    Semver{
+      major: major,
+      minor: minor,
+      patch: patch,
+    }
+  }
+
+  pub fn from_parts(major: Option<i64>, minor: Option<i64>, patch: Option<i64>) -> Option<Semver> {
+    match (major, minor, patch) {
+      (Some(major), Some(minor), Some(patch)) => Some(Semver::new(major, minor, patch)),
+      _ => None,
+    }
+  }
+
+  // Bump the specified component and reset every lower component to zero.
+  pub fn bump(&self, part: Bump) -> Semver {
+    match part {
+      Bump::Major => Semver::new(self.major + 1, 0, 0),
+      Bump::Minor => Semver::new(self.major, self.minor + 1, 0),
+      Bump::Patch => Semver::new(self.major, self.minor, self.patch + 1),
+    }
+  }
+}
+
+impl fmt::Display for Semver {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bump_components() {
+    let v = Semver::new(1, 2, 3);
+    assert_eq!(Semver::new(1, 2, 4), v.bump(Bump::Patch));
+    assert_eq!(Semver::new(1, 3, 0), v.bump(Bump::Minor));
+    assert_eq!(Semver::new(2, 0, 0), v.bump(Bump::Major));
+    assert_eq!("2.0.0", v.bump(Bump::Major).to_string());
+  }
+
+  #[test]
+  fn ordering() {
+    assert!(Semver::new(1, 2, 3) < Semver::new(1, 2, 4));
+    assert!(Semver::new(1, 2, 3) < Semver::new(1, 3, 0));
+    assert!(Semver::new(1, 9, 9) < Semver::new(2, 0, 0));
+    assert!(Semver::new(1, 2, 3) == Semver::new(1, 2, 3));
+  }
+
+  #[test]
+  fn from_parts() {
+    assert_eq!(Some(Semver::new(0, 1, 2)), Semver::from_parts(Some(0), Some(1), Some(2)));
+    assert_eq!(None, Semver::from_parts(Some(0), None, Some(2)));
+    assert_eq!(None, Semver::from_parts(None, None, None));
+  }
+
+}
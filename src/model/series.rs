@@ -0,0 +1,31 @@
+use std::collections;
+
+use warp;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+use crate::model::entry;
+
+// A series together with its own attributes, which describe the series
+// rather than any version of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Series {
+  #[serde(flatten)]
+  pub entry: entry::Entry,
+  pub attrs: collections::HashMap<String, serde_json::Value>,
+}
+
+impl Series {
+  pub fn new(entry: entry::Entry, attrs: collections::HashMap<String, serde_json::Value>) -> Series {
+    Series{
+      entry: entry,
+      attrs: attrs,
+    }
+  }
+}
+
+impl warp::Reply for Series {
+  fn into_response(self) -> warp::reply::Response {
+    warp::reply::Response::new(json!(self).to_string().into())
+  }
+}
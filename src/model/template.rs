@@ -0,0 +1,202 @@
+use std::fmt;
+
+use serde::{Serialize, Deserialize, Serializer, Deserializer, de};
+
+use crate::store;
+use crate::model::entry;
+
+const MAX_LENGTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+  Key,
+  Value,
+  Token(Option<usize>),
+  Major,
+  Minor,
+  Patch,
+  Semver,
+  Calver,
+  Prerelease,
+  Build,
+}
+
+impl Field {
+  fn parse(s: &str) -> Result<Field, store::error::Error> {
+    let (name, arg) = match s.split_once(':') {
+      Some((name, arg)) => (name, Some(arg)),
+      None => (s, None),
+    };
+    let field = match name {
+      "key"        => Field::Key,
+      "value"      => Field::Value,
+      "token"      => match arg {
+        Some(arg) => match arg.parse::<usize>() {
+          Ok(n) if n > 0 => return Ok(Field::Token(Some(n))),
+          _ => return Err(store::error::Error::InvalidOperationError(format!("Invalid token length in template: {:?}", arg))),
+        },
+        None => Field::Token(None),
+      },
+      "major"      => Field::Major,
+      "minor"      => Field::Minor,
+      "patch"      => Field::Patch,
+      "semver"     => Field::Semver,
+      "calver"     => Field::Calver,
+      "prerelease" => Field::Prerelease,
+      "build"      => Field::Build,
+      _            => return Err(store::error::Error::InvalidOperationError(format!("Unknown template field: {:?}", name))),
+    };
+    match arg {
+      Some(_) => Err(store::error::Error::InvalidOperationError(format!("Template field does not accept an argument: {:?}", name))),
+      None => Ok(field),
+    }
+  }
+
+  fn render(&self, entry: &entry::Entry) -> String {
+    match self {
+      Field::Key => entry.key.to_owned(),
+      Field::Value => entry.value.to_string(),
+      Field::Token(n) => match (&entry.token, n) {
+        (Some(token), Some(n)) => token.chars().take(*n).collect(),
+        (Some(token), None) => token.to_owned(),
+        (None, _) => String::new(),
+      },
+      Field::Major => entry.semver.map(|v| v.major.to_string()).unwrap_or_default(),
+      Field::Minor => entry.semver.map(|v| v.minor.to_string()).unwrap_or_default(),
+      Field::Patch => entry.semver.map(|v| v.patch.to_string()).unwrap_or_default(),
+      Field::Semver => entry.semver.map(|v| v.to_string()).unwrap_or_default(),
+      Field::Calver => entry.calver.as_ref().and_then(|c| c.version()).unwrap_or_default(),
+      Field::Prerelease => entry.prerelease.as_ref().map(|p| p.to_string()).unwrap_or_default(),
+      Field::Build => entry.build.clone().unwrap_or_default(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+  Literal(String),
+  Field(Field),
+}
+
+// A template used to format the version of a series, e.g., "v{value}" or
+// "1.{value}.0-{token:7}". Fields are enclosed in braces; literal braces
+// are written as "{{" and "}}".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+  source: String,
+  segments: Vec<Segment>,
+}
+
+impl Template {
+  pub fn parse(source: &str) -> Result<Template, store::error::Error> {
+    if source.len() == 0 || source.len() > MAX_LENGTH {
+      return Err(store::error::Error::InvalidOperationError(format!("Template must be between 1 and {} bytes", MAX_LENGTH)));
+    }
+
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut literal = String::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+      match c {
+        '{' if chars.peek() == Some(&'{') => {
+          chars.next();
+          literal.push('{');
+        },
+        '}' if chars.peek() == Some(&'}') => {
+          chars.next();
+          literal.push('}');
+        },
+        '{' => {
+          let mut name = String::new();
+          loop {
+            match chars.next() {
+              Some('}') => break,
+              Some(c) => name.push(c),
+              None => return Err(store::error::Error::InvalidOperationError(format!("Unterminated template field: {:?}", name))),
+            }
+          }
+          if literal.len() > 0 {
+            segments.push(Segment::Literal(literal));
+            literal = String::new();
+          }
+          segments.push(Segment::Field(Field::parse(name.trim())?));
+        },
+        '}' => return Err(store::error::Error::InvalidOperationError("Unmatched '}' in template".to_string())),
+        c => literal.push(c),
+      }
+    }
+    if literal.len() > 0 {
+      segments.push(Segment::Literal(literal));
+    }
+
+    Ok(Template{
+      source: source.to_string(),
+      segments: segments,
+    })
+  }
+
+  pub fn render(&self, entry: &entry::Entry) -> String {
+    let mut res = String::new();
+    for segment in &self.segments {
+      match segment {
+        Segment::Literal(s) => res.push_str(s),
+        Segment::Field(f) => res.push_str(&f.render(entry)),
+      }
+    }
+    res
+  }
+}
+
+impl fmt::Display for Template {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.source)
+  }
+}
+
+// Templates are represented by their source.
+impl Serialize for Template {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.source)
+  }
+}
+
+impl<'de> Deserialize<'de> for Template {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Template, D::Error> {
+    let source = String::deserialize(deserializer)?;
+    Template::parse(&source).map_err(de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::semver;
+
+  #[test]
+  fn render() {
+    let ent = entry::Entry::new("a", 0, Some("d261470109f2e3a1".to_string()), 42);
+    assert_eq!("v42", Template::parse("v{value}").unwrap().render(&ent));
+    assert_eq!("1.42.0-d261470", Template::parse("1.{value}.0-{token:7}").unwrap().render(&ent));
+    assert_eq!("a@d261470109f2e3a1", Template::parse("{key}@{token}").unwrap().render(&ent));
+    assert_eq!("{42}", Template::parse("{{{value}}}").unwrap().render(&ent));
+
+    let ent = entry::Entry::new_semver("a", 0, None, 3, semver::Semver::new(1, 2, 0));
+    assert_eq!("v1.2.0 (3)", Template::parse("v{semver} ({value})").unwrap().render(&ent));
+    assert_eq!("1-2-0-", Template::parse("{major}-{minor}-{patch}-{token:7}").unwrap().render(&ent));
+  }
+
+  #[test]
+  fn parse() {
+    assert!(Template::parse("").is_err());
+    assert!(Template::parse("v{value").is_err());
+    assert!(Template::parse("v{value}}").is_err());
+    assert!(Template::parse("v{unknown}").is_err());
+    assert!(Template::parse("v{value:7}").is_err());
+    assert!(Template::parse("v{token:0}").is_err());
+    assert!(Template::parse("v{token:x}").is_err());
+    assert_eq!("v{value}", Template::parse("v{value}").unwrap().to_string());
+    assert_eq!("\"v{value}\"", serde_json::to_string(&Template::parse("v{value}").unwrap()).unwrap());
+    assert!(serde_json::from_str::<Template>("\"v{unknown}\"").is_err());
+  }
+
+}
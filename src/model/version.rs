@@ -0,0 +1,52 @@
+use std::collections;
+
+use warp;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use tokio_postgres;
+
+use crate::store;
+use crate::model::entry;
+use crate::model::attrs;
+
+// A version of a series together with the attributes of the token that
+// produced it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Version {
+  #[serde(flatten)]
+  pub entry: entry::Entry,
+  pub attrs: collections::HashMap<String, serde_json::Value>,
+}
+
+impl Version {
+  pub fn new(entry: entry::Entry, attrs: collections::HashMap<String, serde_json::Value>) -> Version {
+    Version{
+      entry: entry,
+      attrs: attrs,
+    }
+  }
+  
+  // Unmarshal a version along with the attributes of its token, which are
+  // aggregated into parallel arrays of names, kinds and values. The arrays
+  // are null when the token has no attributes.
+  pub fn unmarshal(row: &tokio_postgres::Row) -> Result<Version, store::error::Error> {
+    let names: Option<Vec<String>> = row.try_get("attr_names")?;
+    let kinds: Option<Vec<String>> = row.try_get("attr_kinds")?;
+    let values: Option<Vec<String>> = row.try_get("attr_values")?;
+    
+    let mut attrs: collections::HashMap<String, serde_json::Value> = collections::HashMap::new();
+    if let (Some(names), Some(kinds), Some(values)) = (names, kinds, values) {
+      for ((name, kind), value) in names.into_iter().zip(kinds).zip(values) {
+        attrs.insert(name, attrs::decode(&kind, value)?);
+      }
+    }
+    
+    Ok(Version::new(entry::Entry::unmarshal_version(row)?, attrs))
+  }
+}
+
+impl warp::Reply for Version {
+  fn into_response(self) -> warp::reply::Response {
+    warp::reply::Response::new(json!(self).to_string().into())
+  }
+}
@@ -2,23 +2,33 @@ use std::io;
 use std::fmt;
 
 use url;
+use bcrypt;
 use bb8;
 use warp;
 use tokio_postgres;
 
 use crate::acl;
+use crate::model::schema;
 use crate::upgrade;
 
 #[derive(Debug)]
 pub enum Error {
   NotFoundError,
   MarshalError,
+  InvalidOperationError(String),
+  ConflictError(String),
+  LimitExceededError(String),
+  PreconditionFailedError(String),
+  PayloadTooLargeError(String),
+  ValidationError(Vec<schema::Violation>),
   URLParseError(url::ParseError),
   IOError(io::Error),
   PostgresError(tokio_postgres::Error),
   ConnectionError(bb8::RunError<tokio_postgres::Error>),
   ScopeError(acl::scope::Error),
   UpgradeError(upgrade::error::Error),
+  HashError(bcrypt::BcryptError),
+  TaskError(tokio::task::JoinError),
 }
 
 impl warp::reject::Reject for Error {}
@@ -59,17 +69,37 @@ impl From<upgrade::error::Error> for Error {
   }
 }
 
+impl From<bcrypt::BcryptError> for Error {
+  fn from(error: bcrypt::BcryptError) -> Self {
+    Self::HashError(error)
+  }
+}
+
+impl From<tokio::task::JoinError> for Error {
+  fn from(error: tokio::task::JoinError) -> Self {
+    Self::TaskError(error)
+  }
+}
+
 impl fmt::Display for Error {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
       Self::NotFoundError => "not_found".fmt(f),
       Self::MarshalError => "marshal_error".fmt(f),
+      Self::InvalidOperationError(msg) => write!(f, "Invalid operation: {}", msg),
+      Self::ConflictError(msg) => write!(f, "Conflict: {}", msg),
+      Self::LimitExceededError(msg) => write!(f, "Limit exceeded: {}", msg),
+      Self::PreconditionFailedError(msg) => write!(f, "Precondition failed: {}", msg),
+      Self::PayloadTooLargeError(msg) => write!(f, "Payload too large: {}", msg),
+      Self::ValidationError(violations) => write!(f, "Validation failed: {} violations", violations.len()),
       Self::URLParseError(err) => err.fmt(f),
       Self::IOError(err) => err.fmt(f),
       Self::PostgresError(err) => err.fmt(f),
       Self::ConnectionError(err) => err.fmt(f),
       Self::ScopeError(err) => err.fmt(f),
       Self::UpgradeError(err) => err.fmt(f),
+      Self::HashError(err) => err.fmt(f),
+      Self::TaskError(err) => err.fmt(f),
     }
   }
 }
@@ -7,22 +7,35 @@ use bb8_postgres;
 use tokio_postgres;
 use tokio::runtime;
 use futures::{pin_mut, TryStreamExt};
+use serde_json;
 
 use crate::model::account;
 use crate::model::entry;
+use crate::model::semver;
+use crate::model::calver;
+use crate::model::page;
+use crate::model::version;
+use crate::model::query;
+use crate::model::audit;
+use crate::model::alias;
+use crate::model::attrs;
+use crate::model::schema;
+use crate::model::limits;
 use crate::model::apikey;
 use crate::upgrade;
 
 const MAX_RESULTS: usize = 500;
+const MAX_BATCH: usize = 100;
 
 #[derive(Debug, Clone)]
 pub struct Store {
   pool: bb8::Pool<bb8_postgres::PostgresConnectionManager<tokio_postgres::NoTls>>,
+  limits: limits::Limits,
 }
 
 impl Store {
   
-  pub async fn new(dsn: &str) -> Result<Store, error::Error> {
+  pub async fn new(dsn: &str, limits: limits::Limits) -> Result<Store, error::Error> {
     let config: tokio_postgres::config::Config = str::parse(dsn)?;
     let manager = bb8_postgres::PostgresConnectionManager::new(config, tokio_postgres::NoTls);
     let pool = bb8::Pool::builder()
@@ -30,7 +43,7 @@ impl Store {
       .build(manager)
       .await?;
     
-    return Ok(Store{pool});
+    return Ok(Store{pool, limits});
   }
   
   pub async fn migrate<P: AsRef<path::Path>>(&self, dir: P) -> Result<Vec<usize>, error::Error> {
@@ -63,19 +76,26 @@ impl Store {
   }
   
   pub async fn store_authorization(&self, auth: &apikey::Authorization) -> Result<apikey::Authorization, error::Error> {
-    let mut client = self.pool.get().await?;
-    let tx = client.transaction().await?;
-    
     let account_id = match auth.account_id {
       Some(account_id) => account_id,
       None => return Err(error::Error::MarshalError),
     };
+    // only a hash of the secret is stored
+    let hash = match &auth.api_key.secret {
+      Some(secret) => hash_secret(secret.to_owned()).await?,
+      None => return Err(error::Error::MarshalError),
+    };
+    
+    let mut client = self.pool.get().await?;
+    let tx = client.transaction().await?;
     
     let api_key_id: i64 = match tx.query_one(
-      "INSERT INTO mn_api_key (key, secret) VALUES ($1, $2) RETURNING id",
+      "INSERT INTO mn_api_key (key, secret, not_before, expires_at) VALUES ($1, $2, $3, $4) RETURNING id",
       &[
         &auth.api_key.key,
-        &auth.api_key.secret,
+        &hash,
+        &auth.not_before,
+        &auth.expires_at,
       ]
     ).await {
       Ok(row) => row.try_get(0)?,
@@ -97,6 +117,8 @@ impl Store {
       account_id: Some(account_id),
       scopes: auth.scopes.clone(),
       api_key: auth.api_key.with_id(api_key_id),
+      not_before: auth.not_before,
+      expires_at: auth.expires_at,
     })
   }
   
@@ -128,34 +150,105 @@ impl Store {
     Ok(())
   }
   
+  // Delete API Keys which expired before the cutoff, along with their grants.
+  pub async fn purge_api_keys(&self, cutoff: chrono::DateTime<chrono::Utc>) -> Result<u64, error::Error> {
+    let mut client = self.pool.get().await?;
+    let tx = client.transaction().await?;
+    
+    tx.execute("
+      DELETE FROM mn_account_r_api_key AS r USING mn_api_key AS k
+      WHERE r.api_key_id = k.id AND k.expires_at < $1",
+      &[
+        &cutoff,
+      ]
+    )
+    .await?;
+    let n = tx.execute("DELETE FROM mn_api_key WHERE expires_at < $1", &[&cutoff]).await?;
+    
+    tx.commit().await?;
+    Ok(n)
+  }
+  
+  // Hash API Key secrets which are still stored in plaintext, returning the
+  // number of secrets hashed. Secrets which are hashed by a request in the
+  // meantime are left alone.
+  pub async fn hash_plaintext_secrets(&self) -> Result<u64, error::Error> {
+    let client = self.pool.get().await?;
+    
+    let rows = client.query("
+      SELECT id, secret FROM mn_api_key
+      WHERE NOT (length(secret) = 60 AND secret LIKE '$2%')",
+      &[]
+    )
+    .await?;
+    
+    let mut n: u64 = 0;
+    for row in rows {
+      let (id, stored): (i64, String) = (row.try_get(0)?, row.try_get(1)?);
+      n += client.execute("
+        UPDATE mn_api_key SET secret = $3, updated_at = now()
+        WHERE id = $1 AND secret = $2",
+        &[
+          &id,
+          &stored,
+          &hash_secret(stored.to_owned()).await?,
+        ]
+      )
+      .await?;
+    }
+    
+    Ok(n)
+  }
+  
   pub async fn verify_authorization(&self, key: String, secret: String) -> Result<apikey::Authorization, error::Error> {
     let client = self.pool.get().await?;
     
     // NOTE: this is modeled as M:N, but we expect a single result and always
     // return the first record. we should reevalute this handling at some point.
     let stream = client.query_raw("
-      SELECT k.id, k.key, k.secret, r.account_id, r.scopes FROM mn_api_key AS k
+      SELECT k.id, k.key, k.secret, r.account_id, r.scopes, k.not_before, k.expires_at FROM mn_api_key AS k
       INNER JOIN mn_account_r_api_key AS r ON r.api_key_id = k.id
-      WHERE k.key = $1 AND k.secret = $2",
+      WHERE k.key = $1",
       slice_iter(&[
         &key,
-        &secret,
       ])
     )
     .await?;
     pin_mut!(stream);
     
-    match stream.try_next().await? {
-      Some(row) => Ok(apikey::Authorization::unmarshal(&row)?),
-      None => Err(error::Error::NotFoundError),
+    let row = match stream.try_next().await? {
+      Some(row) => row,
+      None => return Err(error::Error::NotFoundError),
+    };
+    let stored: String = row.try_get(2)?;
+    if !verify_secret(secret.to_owned(), stored.to_owned()).await? {
+      return Err(error::Error::NotFoundError);
+    }
+    let auth = apikey::Authorization::unmarshal(&row)?;
+    
+    // secrets which were stored before they were hashed are hashed when the
+    // service starts; one which is used before then is hashed right away
+    if !apikey::is_hashed(&stored) {
+      client.execute("
+        UPDATE mn_api_key SET secret = $3, updated_at = now()
+        WHERE id = $1 AND secret = $2",
+        &[
+          &auth.api_key.id,
+          &stored,
+          &hash_secret(secret).await?,
+        ]
+      )
+      .await?;
     }
+    
+    Ok(auth)
   }
   
   pub async fn fetch_every_authorization_for_account(&self, account_id: i64) -> Result<Vec<apikey::Authorization>, error::Error> {
     let client = self.pool.get().await?;
     
     let rows = client.query("
-      SELECT k.id, k.key, k.secret, r.account_id, r.scopes FROM mn_api_key AS k
+      SELECT k.id, k.key, k.secret, r.account_id, r.scopes, k.not_before, k.expires_at FROM mn_api_key AS k
       INNER JOIN mn_account_r_api_key AS r ON r.api_key_id = k.id
       WHERE r.account_id = $1
       ORDER BY k.created_at
@@ -179,7 +272,7 @@ impl Store {
     let client = self.pool.get().await?;
     
     let stream = client.query_raw("
-      SELECT k.id, k.key, k.secret, r.account_id, r.scopes FROM mn_api_key AS k
+      SELECT k.id, k.key, k.secret, r.account_id, r.scopes, k.not_before, k.expires_at FROM mn_api_key AS k
       INNER JOIN mn_account_r_api_key AS r ON r.api_key_id = k.id
       WHERE r.account_id = $1 AND k.key = $2",
       slice_iter(&[
@@ -219,22 +312,23 @@ impl Store {
   
   pub async fn _store_entry(&self, ent: &entry::Entry) -> Result<(), error::Error> {
     let client = self.pool.get().await?;
+    let (major, minor, patch) = semver_parts(&ent.semver);
     
     client.execute("
-      INSERT INTO mn_entry (key, creator_id, token, value) VALUES ($1, $2, $3, $4)
-      ON CONFLICT (key, creator_id) DO UPDATE SET token = $3, value = $4, updated_at = now()",
+      INSERT INTO mn_entry (key, creator_id, token, value, kind, major, minor, patch, step, max) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+      ON CONFLICT (key, creator_id) DO UPDATE SET token = $3, value = $4, kind = $5, major = $6, minor = $7, patch = $8, step = $9, max = $10, updated_at = now()",
       &[
-        &ent.key, &ent.creator_id, &ent.token, &ent.value,
+        &ent.key, &ent.creator_id, &ent.token, &ent.value, &ent.kind.to_string(), &major, &minor, &patch, &ent.step, &ent.max,
       ]
     )
     .await?;
     
     if let Some(token) = &ent.token {
       client.execute("
-        INSERT INTO mn_entry_version (key, creator_id, token, value) VALUES ($1, $2, $3, $4)
-        ON CONFLICT (key, creator_id, token) DO UPDATE SET value = $4, updated_at = now()",
+        INSERT INTO mn_entry_version (key, creator_id, token, value, kind, major, minor, patch) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (key, creator_id, token) DO UPDATE SET value = $4, kind = $5, major = $6, minor = $7, patch = $8, updated_at = now()",
         &[
-          &ent.key, &ent.creator_id, &token, &ent.value,
+          &ent.key, &ent.creator_id, &token, &ent.value, &ent.kind.to_string(), &major, &minor, &patch,
         ]
       )
       .await?;
@@ -243,12 +337,68 @@ impl Store {
     Ok(())
   }
   
+  pub async fn create_entry(&self, ent: &entry::Entry) -> Result<entry::Entry, error::Error> {
+    let client = self.pool.get().await?;
+    let (major, minor, patch) = semver_parts(&ent.semver);
+    let (period, utc_offset, _, _) = calver_parts(&ent.calver);
+    let timezone = ent.calver.as_ref().and_then(|c| c.timezone).map(|tz| tz.name());
+    
+    if self.resolve_key(&*client, ent.creator_id, &ent.key).await? != ent.key {
+      return Err(error::Error::ConflictError(format!("Series key is an alias: {:?}", ent.key)));
+    }
+    
+    let row = client.query_opt("
+      INSERT INTO mn_entry (key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+      ON CONFLICT (key, creator_id) DO NOTHING
+      RETURNING created_at, updated_at",
+      &[
+        &ent.key, &ent.creator_id, &ent.token, &ent.value, &ent.kind.to_string(), &major, &minor, &patch, &ent.step, &ent.max, &ent.description, &ent.template.as_ref().map(|t| t.to_string()), &period, &utc_offset, &timezone,
+      ]
+    )
+    .await?;
+    
+    match row {
+      Some(row) => Ok(ent.with_timestamps(row.try_get(0)?, row.try_get(1)?)),
+      None => Err(error::Error::ConflictError(format!("Series already exists: {:?}", ent.key))),
+    }
+  }
+  
+  pub async fn list_entries(&self, account_id: i64, filter: &entry::Filter) -> Result<page::Page<entry::Entry>, error::Error> {
+    let client = self.pool.get().await?;
+    let limit = page::limit(filter.limit);
+    let prefix = filter.prefix.clone().unwrap_or_default();
+    let cursor = filter.cursor.clone().unwrap_or_default();
+    
+    let rows = client.query("
+      SELECT key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone, period_start, period_value, created_at, updated_at, deleted_at FROM mn_entry
+      WHERE creator_id = $1 AND left(key, length($2)) = $2 AND key > $3 AND (deleted_at IS NOT NULL) = $4
+      ORDER BY key
+      LIMIT $5",
+      &[
+        &account_id,
+        &prefix,
+        &cursor,
+        &filter.archived.unwrap_or(false),
+        &((limit + 1) as i64),
+      ]
+    )
+    .await?;
+    
+    let mut res: Vec<entry::Entry> = Vec::new();
+    for row in rows {
+      res.push(entry::Entry::unmarshal(&row)?);
+    }
+    
+    Ok(page::Page::new(res, limit, |e| e.key.to_owned()))
+  }
+  
   pub async fn fetch_entry(&self, account_id: i64, key: String) -> Result<entry::Entry, error::Error> {
     let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
     
     let stream = client.query_raw("
-      SELECT key, creator_id, token, value FROM mn_entry
-      WHERE key = $1 AND creator_id = $2",
+      SELECT key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone, period_start, period_value, created_at, updated_at, deleted_at FROM mn_entry
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL",
       slice_iter(&[
         &key,
         &account_id,
@@ -265,10 +415,12 @@ impl Store {
   
   pub async fn fetch_entry_version(&self, account_id: i64, key: String, token: String) -> Result<entry::Entry, error::Error> {
     let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
     
     let stream = client.query_raw("
-      SELECT key, creator_id, token, value FROM mn_entry_version
-      WHERE key = $1 AND creator_id = $2 AND token = $3",
+      SELECT v.key, v.creator_id, v.token, v.value, v.kind, v.major, v.minor, v.patch, e.step, e.max, e.description, e.template, e.period, e.utc_offset, e.timezone, v.period_start, v.period_value, v.channel, v.channel_value, v.build, v.created_at, v.updated_at, e.deleted_at FROM mn_entry_version AS v
+      INNER JOIN mn_entry AS e ON e.key = v.key AND e.creator_id = v.creator_id
+      WHERE v.key = $1 AND v.creator_id = $2 AND v.token = $3 AND e.deleted_at IS NULL",
       slice_iter(&[
         &key,
         &account_id,
@@ -279,212 +431,1672 @@ impl Store {
     pin_mut!(stream);
     
     match stream.try_next().await? {
-      Some(row) => Ok(entry::Entry::unmarshal(&row)?),
+      Some(row) => Ok(entry::Entry::unmarshal_version(&row)?),
       None => Err(error::Error::NotFoundError),
     }
   }
   
-  pub async fn delete_entry(&self, account_id: i64, key: String) -> Result<(), error::Error> {
-    let mut client = self.pool.get().await?;
-    let tx = client.transaction().await?;
-    
-    tx.execute("DELETE FROM mn_entry_version WHERE key = $1 AND creator_id = $2", &[&key, &account_id]).await?;
-    tx.execute("DELETE FROM mn_entry WHERE key = $1 AND creator_id = $2", &[&key, &account_id]).await?;
-    
-    tx.commit().await?;
-    Ok(())
-  }
-  
-  pub async fn inc_entry(&self, account_id: i64, key: String, token: Option<String>) -> Result<entry::Entry, error::Error> {
-    let mut client = self.pool.get().await?;
-    let tx = client.transaction().await?;
+  // Resolve the version of a series that was assigned the specified value,
+  // along with the attributes of the token that produced it. Pre-releases
+  // share the value of the version they lead up to, so the final version is
+  // preferred if there is one.
+  pub async fn fetch_entry_version_by_value(&self, account_id: i64, key: String, value: i64) -> Result<version::Version, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
     
-    let stream = tx.query_raw("
-      SELECT key, creator_id, token, value FROM mn_entry
-      WHERE key = $1 AND creator_id = $2
-      FOR UPDATE",
+    let stream = client.query_raw("
+      SELECT v.key, v.creator_id, v.token, v.value, v.kind, v.major, v.minor, v.patch, e.step, e.max, e.description, e.template, e.period, e.utc_offset, e.timezone, v.period_start, v.period_value, v.channel, v.channel_value, v.build, v.created_at, v.updated_at, e.deleted_at FROM mn_entry_version AS v
+      INNER JOIN mn_entry AS e ON e.key = v.key AND e.creator_id = v.creator_id
+      WHERE v.key = $1 AND v.creator_id = $2 AND v.value = $3 AND e.deleted_at IS NULL
+      ORDER BY v.channel IS NOT NULL, v.channel_value DESC, v.created_at DESC
+      LIMIT 1",
       slice_iter(&[
         &key,
         &account_id,
+        &value,
       ])
     )
     .await?;
     pin_mut!(stream);
     
     let entry = match stream.try_next().await? {
-      Some(row) => entry::Entry::unmarshal(&row)?,
-      None => entry::Entry::new(&key, 1, None, 0),
+      Some(row) => entry::Entry::unmarshal_version(&row)?,
+      None => return Err(error::Error::NotFoundError),
+    };
+    let attrs = match &entry.token {
+      Some(token) => self.fetch_token_attrs(account_id, key, token.to_owned()).await?.attrs,
+      None => collections::HashMap::new(),
     };
     
-    let update = if let Some(tok) = &token {
-      if let Some(upd) = entry.next_with_token(tok) {
-        upd
-      }else{
-        entry.clone()
-      }
-    }else{
-      entry.next()
+    Ok(version::Version::new(entry, attrs))
+  }
+  
+  pub async fn list_entry_versions(&self, account_id: i64, key: String, filter: &entry::VersionFilter) -> Result<page::Page<entry::Entry>, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let limit = page::limit(filter.limit);
+    let (after_value, after_token) = match filter.parse_cursor()? {
+      Some((value, token)) => (Some(value), Some(token)),
+      None => (None, None),
     };
     
-    tx.execute("
-      INSERT INTO mn_entry (key, creator_id, token, value) VALUES ($1, $2, $3, $4)
-      ON CONFLICT (key, creator_id) DO UPDATE SET token = $3, value = $4",
+    let rows = client.query("
+      SELECT v.key, v.creator_id, v.token, v.value, v.kind, v.major, v.minor, v.patch, e.step, e.max, e.description, e.template, e.period, e.utc_offset, e.timezone, v.period_start, v.period_value, v.channel, v.channel_value, v.build, v.created_at, v.updated_at, e.deleted_at FROM mn_entry_version AS v
+      INNER JOIN mn_entry AS e ON e.key = v.key AND e.creator_id = v.creator_id
+      WHERE v.key = $1 AND v.creator_id = $2 AND e.deleted_at IS NULL
+        AND ($3::TIMESTAMPTZ IS NULL OR v.created_at >= $3)
+        AND ($4::TIMESTAMPTZ IS NULL OR v.created_at < $4)
+        AND ($5::BIGINT IS NULL OR v.value >= $5)
+        AND ($6::BIGINT IS NULL OR v.value <= $6)
+        AND ($7::BIGINT IS NULL OR (v.value, v.token) > ($7, $8))
+      ORDER BY v.value, v.token
+      LIMIT $9",
       &[
         &key,
         &account_id,
-        &token,
-        &update.value,
+        &filter.since,
+        &filter.until,
+        &filter.min,
+        &filter.max,
+        &after_value,
+        &after_token,
+        &((limit + 1) as i64),
       ]
     )
     .await?;
     
-    if let Some(token) = &token {
-      tx.execute("
-        INSERT INTO mn_entry_version (key, creator_id, token, value) VALUES ($1, $2, $3, $4)
-        ON CONFLICT (key, creator_id, token) DO UPDATE SET value = $4, updated_at = now()",
-        &[
-          &key,
-          &account_id,
-          &token,
-          &update.value,
-        ]
-      )
-      .await?;
+    let mut res: Vec<entry::Entry> = Vec::new();
+    for row in rows {
+      res.push(entry::Entry::unmarshal_version(&row)?);
     }
     
-    tx.commit().await?;
-    Ok(update)
+    Ok(page::Page::new(res, limit, entry::VersionFilter::cursor))
   }
   
-  pub async fn store_token_attrs(&self, account_id: i64, key: String, token: String, attrs: &collections::HashMap<String, String>) -> Result<(), error::Error> {
-    let mut client = self.pool.get().await?;
-    let tx = client.transaction().await?;
+  // List the version history of a series along with the attributes of the
+  // token that produced each version. Attributes are joined with the page
+  // of versions in the same query.
+  pub async fn list_entry_versions_with_attrs(&self, account_id: i64, key: String, filter: &entry::VersionFilter) -> Result<page::Page<version::Version>, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let limit = page::limit(filter.limit);
+    let (after_value, after_token) = match filter.parse_cursor()? {
+      Some((value, token)) => (Some(value), Some(token)),
+      None => (None, None),
+    };
     
-    for (name, value) in attrs {
-      tx.execute("
-        INSERT INTO mn_token_attr (key, creator_id, token, name, value) VALUES ($1, $2, $3, $4, $5)
-        ON CONFLICT (key, creator_id, token, name) DO UPDATE SET value = $5, updated_at = now()",
-        &[
-          &key,
-          &account_id,
-          &token,
-          &name,
-          &value,
-        ]
-      )
-      .await?;
+    let rows = client.query("
+      SELECT v.*, a.attr_names, a.attr_kinds, a.attr_values FROM (
+        SELECT v.key, v.creator_id, v.token, v.value, v.kind, v.major, v.minor, v.patch, e.step, e.max, e.description, e.template, e.period, e.utc_offset, e.timezone, v.period_start, v.period_value, v.channel, v.channel_value, v.build, v.created_at, v.updated_at, e.deleted_at FROM mn_entry_version AS v
+        INNER JOIN mn_entry AS e ON e.key = v.key AND e.creator_id = v.creator_id
+        WHERE v.key = $1 AND v.creator_id = $2 AND e.deleted_at IS NULL
+          AND ($3::TIMESTAMPTZ IS NULL OR v.created_at >= $3)
+          AND ($4::TIMESTAMPTZ IS NULL OR v.created_at < $4)
+          AND ($5::BIGINT IS NULL OR v.value >= $5)
+          AND ($6::BIGINT IS NULL OR v.value <= $6)
+          AND ($7::BIGINT IS NULL OR (v.value, v.token) > ($7, $8))
+        ORDER BY v.value, v.token
+        LIMIT $9
+      ) AS v
+      LEFT JOIN LATERAL (
+        SELECT array_agg(t.name) AS attr_names, array_agg(t.kind) AS attr_kinds, array_agg(t.value) AS attr_values FROM mn_token_attr AS t
+        WHERE t.key = v.key AND t.creator_id = v.creator_id AND t.token = v.token
+      ) AS a ON true
+      ORDER BY v.value, v.token",
+      &[
+        &key,
+        &account_id,
+        &filter.since,
+        &filter.until,
+        &filter.min,
+        &filter.max,
+        &after_value,
+        &after_token,
+        &((limit + 1) as i64),
+      ]
+    )
+    .await?;
+    
+    let mut res: Vec<version::Version> = Vec::new();
+    for row in rows {
+      res.push(version::Version::unmarshal(&row)?);
     }
     
-    tx.commit().await?;
-    Ok(())
+    Ok(page::Page::new(res, limit, |v| entry::VersionFilter::cursor(&v.entry)))
   }
   
-  pub async fn fetch_token_attrs(&self, account_id: i64, key: String, token: String) -> Result<collections::HashMap<String, String>, error::Error> {
+  // Query the versions of a series, or of every series in the account when
+  // no series is provided, whose token attributes match every predicate in
+  // the filter. The attributes of each version are returned with it.
+  pub async fn query_entry_versions(&self, account_id: i64, key: Option<String>, filter: &query::Query) -> Result<page::Page<version::Version>, error::Error> {
+    let conds = filter.conditions()?;
     let client = self.pool.get().await?;
+    let key = match key {
+      Some(key) => Some(self.resolve_key(&*client, account_id, &key).await?),
+      None => None,
+    };
+    let limit = page::limit(filter.limit);
+    let (after_key, after_value, after_token) = match filter.parse_cursor()? {
+      Some((key, value, token)) => (Some(key), Some(value), Some(token)),
+      None => (None, None, None),
+    };
+    let (order, cmp) = match filter.order.unwrap_or_default() {
+      query::Order::Asc => ("ASC", ">"),
+      query::Order::Desc => ("DESC", "<"),
+    };
+    
+    let mut params: Vec<Box<dyn tokio_postgres::types::ToSql + Sync + Send>> = vec![
+      Box::new(account_id),
+      Box::new(key),
+      Box::new(after_key),
+      Box::new(after_value),
+      Box::new(after_token),
+      Box::new((limit + 1) as i64),
+    ];
+    let mut preds = String::new();
+    for (name, cond) in conds {
+      params.push(Box::new(name));
+      let n = params.len();
+      let attr = format!("SELECT 1 FROM mn_token_attr AS a WHERE a.key = v.key AND a.creator_id = v.creator_id AND a.token = v.token AND a.name = ${}", n);
+      let pred = match cond {
+        query::Condition::Equal(kind, value) => {
+          params.push(Box::new(kind.to_string()));
+          params.push(Box::new(value));
+          format!("EXISTS ({} AND a.kind = ${} AND a.value = ${})", attr, n + 1, n + 2)
+        },
+        query::Condition::Prefix(prefix) => {
+          params.push(Box::new(prefix));
+          format!("EXISTS ({} AND a.kind = 'string' AND left(a.value, length(${})) = ${})", attr, n + 1, n + 1)
+        },
+        query::Condition::Exists(true) => format!("EXISTS ({})", attr),
+        query::Condition::Exists(false) => format!("NOT EXISTS ({})", attr),
+      };
+      preds.push_str(&format!("\n        AND {}", pred));
+    }
+    
+    let sql = format!("
+      SELECT v.key, v.creator_id, v.token, v.value, v.kind, v.major, v.minor, v.patch, e.step, e.max, e.description, e.template, e.period, e.utc_offset, e.timezone, v.period_start, v.period_value, v.channel, v.channel_value, v.build, v.created_at, v.updated_at, e.deleted_at FROM mn_entry_version AS v
+      INNER JOIN mn_entry AS e ON e.key = v.key AND e.creator_id = v.creator_id
+      WHERE v.creator_id = $1 AND e.deleted_at IS NULL
+        AND ($2::VARCHAR IS NULL OR v.key = $2)
+        AND ($3::VARCHAR IS NULL OR (v.key, v.value, v.token) {} ($3, $4, $5)){}
+      ORDER BY v.key {}, v.value {}, v.token {}
+      LIMIT $6", cmp, preds, order, order, order);
+    let refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = params.iter().map(|p| p.as_ref() as &(dyn tokio_postgres::types::ToSql + Sync)).collect();
+    let rows = client.query(sql.as_str(), &refs[..]).await?;
+    
+    let mut entries: Vec<entry::Entry> = Vec::new();
+    for row in rows {
+      entries.push(entry::Entry::unmarshal_version(&row)?);
+    }
     
+    let keys: Vec<String> = entries.iter().map(|e| e.key.to_owned()).collect();
+    let tokens: Vec<String> = entries.iter().map(|e| e.token.clone().unwrap_or_default()).collect();
     let rows = client.query("
-      SELECT name, value FROM mn_token_attr
-      WHERE key = $1 AND creator_id = $2 AND token = $3
-      ORDER BY key",
+      SELECT key, token, name, kind, value FROM mn_token_attr
+      WHERE creator_id = $1 AND (key, token) IN (SELECT * FROM unnest($2::VARCHAR[], $3::VARCHAR[]))",
       &[
-        &key,
         &account_id,
-        &token,
+        &keys,
+        &tokens,
       ]
     )
     .await?;
     
-    let mut map: collections::HashMap<String, String> = collections::HashMap::new();
+    let mut found: collections::HashMap<(String, String), collections::HashMap<String, serde_json::Value>> = collections::HashMap::new();
     for row in rows {
-      map.insert(row.try_get(0)?, row.try_get(1)?);
+      let kind: String = row.try_get(3)?;
+      found.entry((row.try_get(0)?, row.try_get(1)?)).or_default().insert(row.try_get(2)?, attrs::decode(&kind, row.try_get(4)?)?);
+    }
+    
+    let mut res: Vec<version::Version> = Vec::new();
+    for entry in entries {
+      let attrs = found.remove(&(entry.key.to_owned(), entry.token.clone().unwrap_or_default())).unwrap_or_default();
+      res.push(version::Version::new(entry, attrs));
     }
     
-    Ok(map)
+    Ok(page::Page::new(res, limit, |v| query::Query::cursor(&v.entry)))
   }
   
-  pub async fn delete_token_attrs(&self, account_id: i64, key: String, token: String) -> Result<(), error::Error> {
+  // Archive a series. Archived series are hidden but retained, along with
+  // their history, until they are restored or purged. An archived series may
+  // also be purged immediately, in which case its history is deleted; its
+  // audit log is retained. When requested, every branch of the series is
+  // deleted along with it.
+  pub async fn delete_entry(&self, account_id: i64, key: String, del: &entry::Deletion) -> Result<(), error::Error> {
     let mut client = self.pool.get().await?;
-    let tx = client.transaction().await?;
-   
-    tx.execute("
-      DELETE FROM mn_token_attr
-      WHERE key = $1 AND creator_id = $2 AND token = $3",
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    if del.purge {
+      let tx = client.transaction().await?;
+      
+      // only archived series may be purged, so that a series can't be
+      // destroyed by a single request
+      let rows = tx.query("
+        SELECT key FROM mn_entry
+        WHERE creator_id = $2 AND (key = $1 OR ($3 AND left(key, length($1) + 1) = $1 || '/')) AND deleted_at IS NULL
+        FOR UPDATE",
+        &[
+          &key,
+          &account_id,
+          &del.branches,
+        ]
+      )
+      .await?;
+      if let Some(row) = rows.first() {
+        let active: String = row.try_get(0)?;
+        return Err(error::Error::ConflictError(format!("Series must be archived before it is purged: {:?}", active)));
+      }
+      
+      for table in &["mn_entry_version", "mn_entry_channel", "mn_entry_alias", "mn_entry_attr", "mn_token_attr", "mn_entry"] {
+        tx.execute(format!("
+          DELETE FROM {}
+          WHERE creator_id = $2 AND (key = $1 OR ($3 AND left(key, length($1) + 1) = $1 || '/'))", table).as_str(),
+          &[
+            &key,
+            &account_id,
+            &del.branches,
+          ]
+        )
+        .await?;
+      }
+      tx.commit().await?;
+      return Ok(());
+    }
+    
+    client.execute("
+      UPDATE mn_entry SET deleted_at = now()
+      WHERE creator_id = $2 AND (key = $1 OR ($3 AND left(key, length($1) + 1) = $1 || '/')) AND deleted_at IS NULL",
       &[
         &key,
         &account_id,
-        &token,
+        &del.branches,
       ]
     )
     .await?;
     
-    tx.commit().await?;
     Ok(())
   }
   
-  pub async fn store_token_attr(&self, account_id: i64, key: String, token: String, name: &str, value: &str) -> Result<(), error::Error> {
+  // Create a branch of a series, which is a series of its own seeded from
+  // the current state of its parent.
+  pub async fn create_entry_branch(&self, account_id: i64, key: String, branch: &entry::Branch) -> Result<entry::Entry, error::Error> {
+    let parent = self.fetch_entry(account_id, key).await?;
+    self.create_entry(&branch.entry(&parent)?).await
+  }
+  
+  // The key of a branch of a series. The parent may be referred to by an
+  // alias, but branches are always keyed by the key of the parent itself.
+  pub async fn branch_key(&self, account_id: i64, key: &str, name: &str) -> Result<String, error::Error> {
+    let client = self.pool.get().await?;
+    let parent = self.resolve_key(&*client, account_id, key).await?;
+    Ok(entry::Branch::key(&parent, name))
+  }
+  
+  // Increment a branch of a series. Unlike a series, a branch is never
+  // created by incrementing it, since it must have the kind and bounds of
+  // its parent; it must be created explicitly first.
+  pub async fn inc_entry_branch(&self, account_id: i64, key: String, name: String, token: String, inc: &entry::Increment) -> Result<entry::Entry, error::Error> {
     let mut client = self.pool.get().await?;
+    let parent = self.resolve_key(&*client, account_id, &key).await?;
+    let key = entry::Branch::key(&parent, &name);
     let tx = client.transaction().await?;
     
-    tx.execute("
-      INSERT INTO mn_token_attr (key, creator_id, token, name, value) VALUES ($1, $2, $3, $4, $5)
-      ON CONFLICT (key, creator_id, token, name) DO UPDATE SET value = $5, updated_at = now()",
+    let row = tx.query_opt("
+      SELECT key FROM mn_entry
+      WHERE key = $1 AND creator_id = $2
+      FOR UPDATE",
       &[
         &key,
         &account_id,
-        &token,
-        &name,
-        &value,
       ]
     )
     .await?;
+    if row.is_none() {
+      return Err(error::Error::NotFoundError);
+    }
+    
+    let update = self.inc_entry_tx(&tx, account_id, &key, &Some(token), inc).await?;
     
     tx.commit().await?;
-    Ok(())
+    Ok(update)
   }
   
-  pub async fn fetch_token_attr(&self, account_id: i64, key: String, token: String, name: String) -> Result<String, error::Error> {
+  pub async fn store_entry_template(&self, account_id: i64, key: String, fmt: &entry::Formatting) -> Result<entry::Entry, error::Error> {
+    fmt.validate()?;
     let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
     
-    let stream = client.query_raw("
-      SELECT value FROM mn_token_attr
-      WHERE key = $1 AND creator_id = $2 AND token = $3 AND name = $4
-      ORDER BY key",
-      slice_iter(&[
+    let row = client.query_opt("
+      UPDATE mn_entry SET template = $3, updated_at = now()
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL
+      RETURNING key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone, period_start, period_value, created_at, updated_at, deleted_at",
+      &[
         &key,
         &account_id,
-        &token,
-        &name,
-      ])
+        &fmt.template,
+      ]
     )
     .await?;
-    pin_mut!(stream);
     
-    match stream.try_next().await? {
-      Some(row) => Ok(row.try_get(0)?),
+    match row {
+      Some(row) => Ok(entry::Entry::unmarshal(&row)?),
       None => Err(error::Error::NotFoundError),
     }
   }
   
-  pub async fn delete_token_attr(&self, account_id: i64, key: String, token: String, name: String) -> Result<(), error::Error> {
-    let mut client = self.pool.get().await?;
-    let tx = client.transaction().await?;
-   
-    tx.execute("
-      DELETE FROM mn_token_attr
-      WHERE key = $1 AND creator_id = $2 AND token = $3 AND name = $4",
+  pub async fn store_entry_schema(&self, account_id: i64, key: String, schema: &schema::Schema) -> Result<schema::Schema, error::Error> {
+    let schema = schema.compile()?;
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let text = match serde_json::to_string(&schema) {
+      Ok(text) => text,
+      Err(_) => return Err(error::Error::MarshalError),
+    };
+    
+    let n = client.execute("
+      UPDATE mn_entry SET attr_schema = $3, updated_at = now()
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL",
+      &[
+        &key,
+        &account_id,
+        &text,
+      ]
+    )
+    .await?;
+    
+    if n < 1 {
+      return Err(error::Error::NotFoundError);
+    }
+    Ok(schema)
+  }
+  
+  pub async fn fetch_entry_schema(&self, account_id: i64, key: String) -> Result<schema::Schema, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    match self.fetch_entry_schema_tx(&*client, account_id, &key).await? {
+      Some(schema) => Ok(schema),
+      None => Err(error::Error::NotFoundError),
+    }
+  }
+  
+  async fn fetch_entry_schema_tx<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str) -> Result<Option<schema::Schema>, error::Error> {
+    let row = client.query_opt("
+      SELECT attr_schema FROM mn_entry
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    
+    let text: Option<String> = match row {
+      Some(row) => row.try_get(0)?,
+      None => None,
+    };
+    match text {
+      Some(text) => Ok(Some(schema::Schema::parse(&text)?)),
+      None => Ok(None),
+    }
+  }
+  
+  pub async fn delete_entry_schema(&self, account_id: i64, key: String) -> Result<(), error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    client.execute("
+      UPDATE mn_entry SET attr_schema = NULL, updated_at = now()
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL AND attr_schema IS NOT NULL",
       &[
         &key,
         &account_id,
-        &token,
-        &name,
       ]
     )
     .await?;
     
-    tx.commit().await?;
     Ok(())
   }
   
+  // Check attributes being stored for a token against the schema of its
+  // series, if it has one. The attributes the token will retain count
+  // towards those that are required.
+  async fn check_token_attrs<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str, attrs: &collections::HashMap<String, serde_json::Value>, existing: &collections::HashMap<String, serde_json::Value>) -> Result<(), error::Error> {
+    match self.fetch_entry_schema_tx(client, account_id, key).await? {
+      Some(schema) => schema.check(attrs, existing),
+      None => Ok(()),
+    }
+  }
+  
+  // Fetch the attribute limits of an account, which are the limits of the
+  // deployment unless they have been overridden for the account.
+  async fn fetch_account_limits_tx<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64) -> Result<limits::Limits, error::Error> {
+    let row = client.query_opt("
+      SELECT attr_max_value_bytes, attr_max_per_token, attr_max_account_bytes FROM mn_account
+      WHERE id = $1",
+      &[
+        &account_id,
+      ]
+    )
+    .await?;
+    
+    match row {
+      Some(row) => Ok(self.limits.with_overrides(&limits::Overrides::unmarshal(&row)?)),
+      None => Ok(self.limits),
+    }
+  }
+  
+  pub async fn fetch_account_limits(&self, account_id: i64) -> Result<limits::Limits, error::Error> {
+    let client = self.pool.get().await?;
+    self.fetch_account_limits_tx(&*client, account_id).await
+  }
+  
+  pub async fn store_account_limits(&self, account_id: i64, overrides: &limits::Overrides) -> Result<limits::Limits, error::Error> {
+    overrides.validate()?;
+    let client = self.pool.get().await?;
+    
+    let row = client.query_opt("
+      UPDATE mn_account SET attr_max_value_bytes = $2, attr_max_per_token = $3, attr_max_account_bytes = $4, updated_at = now()
+      WHERE id = $1
+      RETURNING attr_max_value_bytes, attr_max_per_token, attr_max_account_bytes",
+      &[
+        &account_id,
+        &overrides.max_value_bytes,
+        &overrides.max_attrs_per_token,
+        &overrides.max_account_bytes,
+      ]
+    )
+    .await?;
+    
+    match row {
+      Some(row) => Ok(self.limits.with_overrides(&limits::Overrides::unmarshal(&row)?)),
+      None => Err(error::Error::NotFoundError),
+    }
+  }
+  
+  // Check that replacing the attributes of a token or series, which are
+  // currently before, with after by storing the changed attributes remains
+  // within the limits of the account. When the attributes grow, writes in the
+  // account are serialized so that concurrent writes to other tokens
+  // cannot exceed the account quota together.
+  async fn check_attr_limits<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, changed: &collections::HashMap<String, serde_json::Value>, before: &collections::HashMap<String, serde_json::Value>, after: &collections::HashMap<String, serde_json::Value>) -> Result<(), error::Error> {
+    let limits = self.fetch_account_limits_tx(client, account_id).await?;
+    limits.check_values(changed)?;
+    if after.len() > before.len() {
+      limits.check_count(after.len())?;
+    }
+    
+    let delta = limits::total(after) - limits::total(before);
+    if delta <= 0 {
+      return Ok(());
+    }
+    
+    client.execute("
+      SELECT pg_advisory_xact_lock($1)",
+      &[
+        &account_id,
+      ]
+    )
+    .await?;
+    let row = client.query_one("
+      SELECT
+        (SELECT COALESCE(SUM(octet_length(name) + octet_length(value)), 0)::BIGINT FROM mn_token_attr WHERE creator_id = $1) +
+        (SELECT COALESCE(SUM(octet_length(name) + octet_length(value)), 0)::BIGINT FROM mn_entry_attr WHERE creator_id = $1)",
+      &[
+        &account_id,
+      ]
+    )
+    .await?;
+    
+    let usage: i64 = row.try_get(0)?;
+    limits.check_usage(usage + delta)
+  }
+  
+  pub async fn restore_entry(&self, account_id: i64, key: String) -> Result<entry::Entry, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    let row = client.query_opt("
+      UPDATE mn_entry SET deleted_at = NULL
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NOT NULL
+      RETURNING key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone, period_start, period_value, created_at, updated_at, deleted_at",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    
+    match row {
+      Some(row) => Ok(entry::Entry::unmarshal(&row)?),
+      None => Err(error::Error::NotFoundError),
+    }
+  }
+  
+  // Permanently delete every series that was archived before the provided
+  // cutoff, along with its history but not its audit log. Returns the number
+  // of series purged.
+  pub async fn purge_entries(&self, cutoff: chrono::DateTime<chrono::Utc>) -> Result<u64, error::Error> {
+    let mut client = self.pool.get().await?;
+    let tx = client.transaction().await?;
+    
+    for table in &["mn_entry_version", "mn_entry_channel", "mn_entry_alias", "mn_entry_attr", "mn_token_attr"] {
+      tx.execute(format!("
+        DELETE FROM {} AS d USING mn_entry AS e
+        WHERE d.key = e.key AND d.creator_id = e.creator_id AND e.deleted_at < $1", table).as_str(),
+        &[
+          &cutoff,
+        ]
+      )
+      .await?;
+    }
+    let n = tx.execute("DELETE FROM mn_entry WHERE deleted_at < $1", &[&cutoff]).await?;
+    
+    tx.commit().await?;
+    Ok(n)
+  }
+  
+  // Rename a series, move it to another account, or both. The series is
+  // moved along with its history, pre-release channels, audit log, its own
+  // attributes and the attributes of its tokens in a single transaction.
+  // Its branches, which are keyed by the key of the series, move with it.
+  pub async fn move_entry(&self, account_id: i64, key: String, mv: &entry::Move) -> Result<entry::Entry, error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let (dest_id, dest_key) = mv.target(account_id, &key)?;
+    let tx = client.transaction().await?;
+    
+    let row = tx.query_opt("
+      SELECT key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone, period_start, period_value, created_at, updated_at, deleted_at FROM mn_entry
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL
+      FOR UPDATE",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    if row.is_none() {
+      return Err(error::Error::NotFoundError);
+    }
+    
+    let row = tx.query_opt("SELECT id FROM mn_account WHERE id = $1", &[&dest_id]).await?;
+    if row.is_none() {
+      return Err(error::Error::NotFoundError);
+    }
+    
+    // the destination must be entirely unused, including by archived series,
+    // attributes of tokens that never produced a version, and aliases; the
+    // branches of the series move with it, so their keys must be unused too
+    let row = tx.query_one("
+      SELECT
+        EXISTS (SELECT 1 FROM mn_entry WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')) OR
+        EXISTS (SELECT 1 FROM mn_entry_version WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')) OR
+        EXISTS (SELECT 1 FROM mn_token_attr WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')) OR
+        EXISTS (SELECT 1 FROM mn_entry_attr WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')) OR
+        EXISTS (SELECT 1 FROM mn_entry_alias WHERE alias = $1 AND creator_id = $2)",
+      &[
+        &dest_key,
+        &dest_id,
+      ]
+    )
+    .await?;
+    let used: bool = row.try_get(0)?;
+    if used {
+      return Err(error::Error::ConflictError(format!("Series already exists: {:?}", dest_key)));
+    }
+    
+    for table in &["mn_entry", "mn_entry_version", "mn_entry_channel", "mn_entry_audit", "mn_entry_attr", "mn_token_attr"] {
+      tx.execute(format!("
+        UPDATE {} SET key = $3 || substr(key, length($1) + 1), creator_id = $4
+        WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')", table).as_str(),
+        &[
+          &key,
+          &account_id,
+          &dest_key,
+          &dest_id,
+        ]
+      )
+      .await?;
+    }
+    
+    // aliases belong to an account, so they follow a renamed series but are
+    // dropped when it moves to another account
+    if dest_id == account_id {
+      tx.execute("
+        UPDATE mn_entry_alias SET key = $3 || substr(key, length($1) + 1)
+        WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')",
+        &[&key, &account_id, &dest_key]).await?;
+    }else{
+      tx.execute("
+        DELETE FROM mn_entry_alias
+        WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')",
+        &[&key, &account_id]).await?;
+    }
+    
+    let row = tx.query_one("
+      SELECT key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone, period_start, period_value, created_at, updated_at, deleted_at FROM mn_entry
+      WHERE key = $1 AND creator_id = $2",
+      &[
+        &dest_key,
+        &dest_id,
+      ]
+    )
+    .await?;
+    let update = entry::Entry::unmarshal(&row)?;
+    
+    tx.commit().await?;
+    Ok(update)
+  }
+  
+  // Manually assign the value of a series and record the change, along with
+  // the API key that made it, in the audit log. Version history is left
+  // untouched.
+  pub async fn set_entry(&self, account_id: i64, key: String, actor: &str, set: &entry::Assignment) -> Result<entry::Entry, error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    
+    let stream = tx.query_raw("
+      SELECT key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone, period_start, period_value, created_at, updated_at, deleted_at FROM mn_entry
+      WHERE key = $1 AND creator_id = $2
+      FOR UPDATE",
+      slice_iter(&[
+        &key,
+        &account_id,
+      ])
+    )
+    .await?;
+    pin_mut!(stream);
+    
+    let entry = match stream.try_next().await? {
+      Some(row) => entry::Entry::unmarshal(&row)?,
+      None => return Err(error::Error::NotFoundError),
+    };
+    if entry.deleted_at.is_some() {
+      return Err(error::Error::NotFoundError);
+    }
+    
+    // the series token is cleared, so the next increment advances the series
+    // from the assigned value regardless of the token it provides
+    let update = set.apply(&entry)?;
+    let (major, minor, patch) = semver_parts(&update.semver);
+    
+    let row = tx.query_one("
+      UPDATE mn_entry SET token = NULL, value = $3, major = $4, minor = $5, patch = $6, updated_at = now()
+      WHERE key = $1 AND creator_id = $2
+      RETURNING created_at, updated_at",
+      &[
+        &key,
+        &account_id,
+        &update.value,
+        &major,
+        &minor,
+        &patch,
+      ]
+    )
+    .await?;
+    let update = update.with_timestamps(row.try_get(0)?, row.try_get(1)?);
+    
+    tx.execute("
+      INSERT INTO mn_entry_audit (key, creator_id, actor, previous_value, value, previous_version, version, forced, reason) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+      &[
+        &key,
+        &account_id,
+        &actor,
+        &entry.value,
+        &update.value,
+        &entry.semver.map(|e| e.to_string()),
+        &update.semver.map(|e| e.to_string()),
+        &set.force,
+        &set.reason,
+      ]
+    )
+    .await?;
+    
+    tx.commit().await?;
+    Ok(update)
+  }
+  
+  pub async fn fetch_entry_audit(&self, account_id: i64, key: String) -> Result<Vec<audit::Audit>, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    let rows = client.query("
+      SELECT id, key, creator_id, actor, previous_value, value, previous_version, version, forced, reason, created_at FROM mn_entry_audit
+      WHERE key = $1 AND creator_id = $2
+      ORDER BY created_at DESC, id DESC
+      LIMIT $3",
+      &[
+        &key,
+        &account_id,
+        &(MAX_RESULTS as i64),
+      ]
+    )
+    .await?;
+    
+    let mut res: Vec<audit::Audit> = Vec::new();
+    for row in rows {
+      res.push(audit::Audit::unmarshal(&row)?);
+    }
+    
+    Ok(res)
+  }
+  
+  pub async fn inc_entry(&self, account_id: i64, key: String, token: Option<String>, inc: &entry::Increment) -> Result<entry::Entry, error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    
+    let update = self.inc_entry_tx(&tx, account_id, &key, &token, inc).await?;
+    
+    tx.commit().await?;
+    Ok(update)
+  }
+  
+  // Increment several series for the same token in a single transaction;
+  // either every series is advanced or none are. Series are locked in key
+  // order so that concurrent batches cannot deadlock, and the results are
+  // returned in that order.
+  pub async fn inc_entries(&self, account_id: i64, keys: &Vec<String>, token: String, inc: &entry::Increment) -> Result<Vec<entry::Entry>, error::Error> {
+    if keys.len() > MAX_BATCH {
+      return Err(error::Error::InvalidOperationError(format!("Too many series provided: {} > {}", keys.len(), MAX_BATCH)));
+    }
+    
+    let mut client = self.pool.get().await?;
+    
+    // aliases are resolved first, since several may refer to the same series
+    let mut resolved: Vec<String> = Vec::new();
+    for key in keys {
+      resolved.push(self.resolve_key(&*client, account_id, key).await?);
+    }
+    let mut keys = resolved;
+    keys.sort();
+    keys.dedup();
+    if keys.len() == 0 {
+      return Err(error::Error::InvalidOperationError("No series provided".to_string()));
+    }
+    if keys.len() > MAX_BATCH {
+      return Err(error::Error::InvalidOperationError(format!("Too many series provided: {} > {}", keys.len(), MAX_BATCH)));
+    }
+    
+    let tx = client.transaction().await?;
+    
+    let token = Some(token);
+    let mut res: Vec<entry::Entry> = Vec::new();
+    for key in &keys {
+      res.push(self.inc_entry_tx(&tx, account_id, key, &token, inc).await?);
+    }
+    
+    tx.commit().await?;
+    Ok(res)
+  }
+  
+  async fn inc_entry_tx(&self, tx: &tokio_postgres::Transaction<'_>, account_id: i64, key: &str, token: &Option<String>, inc: &entry::Increment) -> Result<entry::Entry, error::Error> {
+    if let Some(token) = token {
+      entry::validate_token(token)?;
+    }
+    
+    let stream = tx.query_raw("
+      SELECT key, creator_id, token, value, kind, major, minor, patch, step, max, description, template, period, utc_offset, timezone, period_start, period_value, created_at, updated_at, deleted_at FROM mn_entry
+      WHERE key = $1 AND creator_id = $2
+      FOR UPDATE",
+      slice_iter(&[
+        &key,
+        &account_id,
+      ])
+    )
+    .await?;
+    pin_mut!(stream);
+    
+    // a missing series is created as a semantic version series if the first
+    // increment requests a component bump; otherwise it's a plain counter
+    let entry = match stream.try_next().await? {
+      Some(row) => entry::Entry::unmarshal(&row)?,
+      None => {
+        entry::validate_key(key)?;
+        match inc.bump {
+          Some(_) => entry::Entry::new_semver(key, account_id, None, 0, semver::Semver::new(0, 0, 0)),
+          None => entry::Entry::new(key, account_id, None, 0),
+        }
+      },
+    };
+    // an archived series can't be recreated by incrementing it; it must be
+    // restored, or purged, first
+    if entry.deleted_at.is_some() {
+      return Err(error::Error::ConflictError(format!("Series is archived: {:?}", key)));
+    }
+    
+    let bump = match inc.bump {
+      Some(bump) => match entry.kind {
+        entry::Kind::Semver => bump,
+        kind => return Err(error::Error::InvalidOperationError(format!("Cannot bump {} of a {} series", bump, kind))),
+      },
+      None => semver::Bump::Patch,
+    };
+    
+    // a token identifies a single version of the series, so it can't produce
+    // both a release and a pre-release, or pre-releases in several channels;
+    // repeating a token returns the version it already produced, unchanged,
+    // even if the series has since been advanced by other tokens
+    if let Some(tok) = token {
+      match self.fetch_token_channel_tx(tx, account_id, key, tok).await? {
+        Some(used) if used != inc.channel => return Err(error::Error::ConflictError(match used {
+          Some(channel) => format!("Token {:?} already produced a pre-release in channel {:?}", tok, channel),
+          None => format!("Token {:?} already produced a release", tok),
+        })),
+        Some(_) => return self.fetch_entry_version_tx(tx, account_id, key, tok).await,
+        None => {},
+      }
+    }
+    
+    // repeating the last increment is a no-op, so the preconditions, which
+    // were satisfied by the original request, don't apply to it
+    if token.is_none() || *token != entry.token {
+      inc.check_precondition(&entry)?;
+      entry.check_next()?;
+    }
+    
+    if let (Some(channel), Some(tok)) = (&inc.channel, token) {
+      entry::Prerelease::validate_channel(channel)?;
+      return self.inc_entry_channel(tx, &entry, tok, bump, channel, &inc.build).await;
+    }
+    
+    let now = chrono::Utc::now();
+    let update = if let Some(tok) = token {
+      if let Some(upd) = entry.bump_with_token(tok, bump) {
+        upd.with_calendar(now)
+      }else{
+        entry.clone()
+      }
+    }else{
+      entry.bump(bump).with_calendar(now)
+    };
+    let update = update.with_build(inc.build.clone());
+    let (major, minor, patch) = semver_parts(&update.semver);
+    let (_, _, period_start, period_value) = calver_parts(&update.calver);
+    
+    let row = tx.query_one("
+      INSERT INTO mn_entry (key, creator_id, token, value, kind, major, minor, patch, period_start, period_value) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+      ON CONFLICT (key, creator_id) DO UPDATE SET token = $3, value = $4, major = $6, minor = $7, patch = $8, period_start = $9, period_value = $10, updated_at = now()
+      RETURNING created_at, updated_at",
+      &[
+        &key,
+        &account_id,
+        &token,
+        &update.value,
+        &update.kind.to_string(),
+        &major,
+        &minor,
+        &patch,
+        &period_start,
+        &period_value,
+      ]
+    )
+    .await?;
+    let update = update.with_timestamps(row.try_get(0)?, row.try_get(1)?);
+    
+    // the version of a token is never rewritten; if another request produced
+    // it in the meantime this one fails and is rolled back
+    if let Some(token) = token {
+      let n = tx.execute("
+        INSERT INTO mn_entry_version (key, creator_id, token, value, kind, major, minor, patch, channel, channel_value, build, period_start, period_value) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NULL, NULL, $9, $10, $11)
+        ON CONFLICT (key, creator_id, token) DO NOTHING",
+        &[
+          &key,
+          &account_id,
+          &token,
+          &update.value,
+          &update.kind.to_string(),
+          &major,
+          &minor,
+          &patch,
+          &update.build,
+          &period_start,
+          &period_value,
+        ]
+      )
+      .await?;
+      if n == 0 {
+        return Err(error::Error::ConflictError(format!("Token {:?} already produced a version", token)));
+      }
+    }
+    
+    Ok(update)
+  }
+  
+  // Produce a pre-release of the next version of a series in the specified
+  // channel. Each channel maintains its own counter which is tied to the value
+  // of the parent series; once the parent advances (that is, the pre-release
+  // graduates) the channel counter starts over.
+  async fn inc_entry_channel(&self, tx: &tokio_postgres::Transaction<'_>, entry: &entry::Entry, token: &str, bump: semver::Bump, channel: &str, build: &Option<String>) -> Result<entry::Entry, error::Error> {
+    let (major, minor, patch) = semver_parts(&entry.semver);
+    
+    // make sure the parent series exists, even if it has never been released
+    tx.execute("
+      INSERT INTO mn_entry (key, creator_id, token, value, kind, major, minor, patch) VALUES ($1, $2, NULL, $3, $4, $5, $6, $7)
+      ON CONFLICT (key, creator_id) DO NOTHING",
+      &[
+        &entry.key,
+        &entry.creator_id,
+        &entry.value,
+        &entry.kind.to_string(),
+        &major,
+        &minor,
+        &patch,
+      ]
+    )
+    .await?;
+    
+    let stream = tx.query_raw("
+      SELECT token, base, value FROM mn_entry_channel
+      WHERE key = $1 AND creator_id = $2 AND channel = $3
+      FOR UPDATE",
+      slice_iter(&[
+        &entry.key,
+        &entry.creator_id,
+        &channel,
+      ])
+    )
+    .await?;
+    pin_mut!(stream);
+    
+    let number = match stream.try_next().await? {
+      Some(row) => {
+        let (last, base, value): (Option<String>, i64, i64) = (row.try_get(0)?, row.try_get(1)?, row.try_get(2)?);
+        if base != entry.value {
+          1 // the parent has advanced since the last pre-release, start over
+        }else if last.as_deref() == Some(token) {
+          return self.fetch_entry_version_tx(tx, entry.creator_id, &entry.key, token).await; // token matches, no update
+        }else{
+          value + 1
+        }
+      },
+      None => 1,
+    };
+    
+    tx.execute("
+      INSERT INTO mn_entry_channel (key, creator_id, channel, token, base, value) VALUES ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT (key, creator_id, channel) DO UPDATE SET token = $4, base = $5, value = $6, updated_at = now()",
+      &[
+        &entry.key,
+        &entry.creator_id,
+        &channel,
+        &token,
+        &entry.value,
+        &number,
+      ]
+    )
+    .await?;
+    
+    // the pre-release leads up to the version the series would produce now,
+    // including the calendar counter of a CalVer series
+    let update = entry.prerelease(token, bump, channel, number).with_calendar(chrono::Utc::now()).with_build(build.clone());
+    let (major, minor, patch) = semver_parts(&update.semver);
+    let (_, _, period_start, period_value) = calver_parts(&update.calver);
+    
+    let row = tx.query_opt("
+      INSERT INTO mn_entry_version (key, creator_id, token, value, kind, major, minor, patch, channel, channel_value, build, period_start, period_value) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+      ON CONFLICT (key, creator_id, token) DO NOTHING
+      RETURNING created_at, updated_at",
+      &[
+        &update.key,
+        &update.creator_id,
+        &token,
+        &update.value,
+        &update.kind.to_string(),
+        &major,
+        &minor,
+        &patch,
+        &channel,
+        &number,
+        &update.build,
+        &period_start,
+        &period_value,
+      ]
+    )
+    .await?;
+    
+    match row {
+      Some(row) => Ok(update.with_timestamps(row.try_get(0)?, row.try_get(1)?)),
+      None => Err(error::Error::ConflictError(format!("Token {:?} already produced a version", token))),
+    }
+  }
+  
+  // Determine whether a token has already produced a version of a series. If
+  // it has, the channel of that version is returned, which is absent for a
+  // release.
+  async fn fetch_token_channel_tx(&self, tx: &tokio_postgres::Transaction<'_>, account_id: i64, key: &str, token: &str) -> Result<Option<Option<String>>, error::Error> {
+    let row = tx.query_opt("
+      SELECT channel FROM mn_entry_version
+      WHERE key = $1 AND creator_id = $2 AND token = $3",
+      &[
+        &key,
+        &account_id,
+        &token,
+      ]
+    )
+    .await?;
+    match row {
+      Some(row) => Ok(Some(row.try_get(0)?)),
+      None => Ok(None),
+    }
+  }
+  
+  async fn fetch_entry_version_tx(&self, tx: &tokio_postgres::Transaction<'_>, account_id: i64, key: &str, token: &str) -> Result<entry::Entry, error::Error> {
+    let stream = tx.query_raw("
+      SELECT v.key, v.creator_id, v.token, v.value, v.kind, v.major, v.minor, v.patch, e.step, e.max, e.description, e.template, e.period, e.utc_offset, e.timezone, v.period_start, v.period_value, v.channel, v.channel_value, v.build, v.created_at, v.updated_at, e.deleted_at FROM mn_entry_version AS v
+      INNER JOIN mn_entry AS e ON e.key = v.key AND e.creator_id = v.creator_id
+      WHERE v.key = $1 AND v.creator_id = $2 AND v.token = $3 AND e.deleted_at IS NULL",
+      slice_iter(&[
+        &key,
+        &account_id,
+        &token,
+      ])
+    )
+    .await?;
+    pin_mut!(stream);
+    
+    match stream.try_next().await? {
+      Some(row) => Ok(entry::Entry::unmarshal_version(&row)?),
+      None => Err(error::Error::NotFoundError),
+    }
+  }
+  
+  // Resolve a series key which may be an alias to the key of the series it
+  // refers to. Keys which are not aliases are returned unchanged.
+  async fn resolve_key<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str) -> Result<String, error::Error> {
+    let row = client.query_opt("
+      SELECT key FROM mn_entry_alias
+      WHERE alias = $1 AND creator_id = $2",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    
+    match row {
+      Some(row) => Ok(row.try_get(0)?),
+      None => Ok(key.to_string()),
+    }
+  }
+  
+  pub async fn store_entry_alias(&self, account_id: i64, key: String, alias: String) -> Result<alias::Alias, error::Error> {
+    // an alias is used in place of a series key, so it follows the same rules
+    entry::validate_key(&alias)?;
+    
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    
+    let row = tx.query_opt("
+      SELECT key FROM mn_entry
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL
+      FOR SHARE",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    if row.is_none() {
+      return Err(error::Error::NotFoundError);
+    }
+    
+    // the alias must not be in use as a key by anything that would otherwise
+    // be resolved through it, including archived series and their branches
+    let row = tx.query_one("
+      SELECT
+        EXISTS (SELECT 1 FROM mn_entry WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')) OR
+        EXISTS (SELECT 1 FROM mn_entry_version WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')) OR
+        EXISTS (SELECT 1 FROM mn_token_attr WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')) OR
+        EXISTS (SELECT 1 FROM mn_entry_attr WHERE creator_id = $2 AND (key = $1 OR left(key, length($1) + 1) = $1 || '/')) OR
+        EXISTS (SELECT 1 FROM mn_entry_alias WHERE key = $1 AND creator_id = $2)",
+      &[
+        &alias,
+        &account_id,
+      ]
+    )
+    .await?;
+    let used: bool = row.try_get(0)?;
+    if used {
+      return Err(error::Error::ConflictError(format!("Series already exists: {:?}", alias)));
+    }
+    
+    let row = tx.query_opt("
+      INSERT INTO mn_entry_alias (alias, creator_id, key) VALUES ($1, $2, $3)
+      ON CONFLICT (alias, creator_id) DO UPDATE SET key = $3 WHERE mn_entry_alias.key = $3
+      RETURNING alias, creator_id, key, created_at",
+      &[
+        &alias,
+        &account_id,
+        &key,
+      ]
+    )
+    .await?;
+    let res = match row {
+      Some(row) => alias::Alias::unmarshal(&row)?,
+      None => return Err(error::Error::ConflictError(format!("Alias refers to another series: {:?}", alias))),
+    };
+    
+    tx.commit().await?;
+    Ok(res)
+  }
+  
+  pub async fn fetch_entry_aliases(&self, account_id: i64, key: String) -> Result<Vec<alias::Alias>, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    let rows = client.query("
+      SELECT alias, creator_id, key, created_at FROM mn_entry_alias
+      WHERE key = $1 AND creator_id = $2
+      ORDER BY alias
+      LIMIT $3",
+      &[
+        &key,
+        &account_id,
+        &(MAX_RESULTS as i64),
+      ]
+    )
+    .await?;
+    
+    let mut res: Vec<alias::Alias> = Vec::new();
+    for row in rows {
+      res.push(alias::Alias::unmarshal(&row)?);
+    }
+    
+    Ok(res)
+  }
+  
+  pub async fn delete_entry_alias(&self, account_id: i64, key: String, alias: String) -> Result<(), error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    client.execute("
+      DELETE FROM mn_entry_alias
+      WHERE alias = $1 AND creator_id = $2 AND key = $3",
+      &[
+        &alias,
+        &account_id,
+        &key,
+      ]
+    )
+    .await?;
+    
+    Ok(())
+  }
+  
+  // Serialize writes to the attributes of a token, so that preconditions
+  // are checked against the same attribute set that is then written.
+  async fn lock_token_attrs<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str, token: &str) -> Result<(), error::Error> {
+    client.execute("
+      SELECT pg_advisory_xact_lock(hashtext($1 || ':' || $2), hashtext($3))",
+      &[
+        &account_id.to_string(),
+        &key,
+        &token,
+      ]
+    )
+    .await?;
+    Ok(())
+  }
+  
+  // Fetch the attributes of a token along with the entity tag of the set.
+  async fn fetch_token_attrs_tx<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str, token: &str) -> Result<attrs::Attrs, error::Error> {
+    let rows = client.query("
+      SELECT name, kind, value FROM mn_token_attr
+      WHERE key = $1 AND creator_id = $2 AND token = $3",
+      &[
+        &key,
+        &account_id,
+        &token,
+      ]
+    )
+    .await?;
+    
+    let mut map: collections::HashMap<String, serde_json::Value> = collections::HashMap::new();
+    let mut stored: Vec<(String, String, String)> = Vec::new();
+    for row in rows {
+      let (name, kind, value): (String, String, String) = (row.try_get(0)?, row.try_get(1)?, row.try_get(2)?);
+      map.insert(name.to_owned(), attrs::decode(&kind, value.to_owned())?);
+      stored.push((name, kind, value));
+    }
+    
+    let etag = attrs::etag(&stored);
+    Ok(attrs::Attrs::new(map).with_etag(etag))
+  }
+  
+  async fn upsert_token_attrs_tx<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str, token: &str, attrs: &collections::HashMap<String, serde_json::Value>) -> Result<(), error::Error> {
+    for (name, value) in attrs {
+      let (kind, value) = attrs::encode(name, value)?;
+      client.execute("
+        INSERT INTO mn_token_attr (key, creator_id, token, name, kind, value) VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (key, creator_id, token, name) DO UPDATE SET kind = $5, value = $6, updated_at = now()",
+        &[
+          &key,
+          &account_id,
+          &token,
+          &name,
+          &kind.to_string(),
+          &value,
+        ]
+      )
+      .await?;
+    }
+    Ok(())
+  }
+  
+  // Store attributes for a token, returning the entity tag of the updated
+  // attribute set. Attributes which are not provided are retained.
+  pub async fn store_token_attrs(&self, account_id: i64, key: String, token: String, attrs: &collections::HashMap<String, serde_json::Value>, if_match: Option<&str>) -> Result<String, error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_token_attrs(&tx, account_id, &key, &token).await?;
+    
+    let existing = self.fetch_token_attrs_tx(&tx, account_id, &key, &token).await?;
+    existing.check_precondition(if_match)?;
+    let mut after = existing.attrs.clone();
+    after.extend(attrs.clone());
+    self.check_attr_limits(&tx, account_id, attrs, &existing.attrs, &after).await?;
+    self.check_token_attrs(&tx, account_id, &key, attrs, &existing.attrs).await?;
+    self.upsert_token_attrs_tx(&tx, account_id, &key, &token, attrs).await?;
+    let updated = self.fetch_token_attrs_tx(&tx, account_id, &key, &token).await?;
+    
+    tx.commit().await?;
+    Ok(updated.etag.unwrap_or_default())
+  }
+  
+  // Apply a JSON Merge Patch to the attributes of a token. Attributes which
+  // are null in the patch are deleted; others are merged with the existing
+  // value of the attribute. The updated attribute set is returned.
+  pub async fn patch_token_attrs(&self, account_id: i64, key: String, token: String, patch: &serde_json::Value, if_match: Option<&str>) -> Result<attrs::Attrs, error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_token_attrs(&tx, account_id, &key, &token).await?;
+    
+    let existing = self.fetch_token_attrs_tx(&tx, account_id, &key, &token).await?;
+    existing.check_precondition(if_match)?;
+    
+    let changes = attrs::Changes::patch(&existing.attrs, patch)?;
+    
+    self.check_attr_limits(&tx, account_id, &changes.changed, &existing.attrs, &changes.merged).await?;
+    self.check_token_attrs(&tx, account_id, &key, &changes.changed, &changes.retained).await?;
+    self.upsert_token_attrs_tx(&tx, account_id, &key, &token, &changes.changed).await?;
+    if changes.removed.len() > 0 {
+      tx.execute("
+        DELETE FROM mn_token_attr
+        WHERE key = $1 AND creator_id = $2 AND token = $3 AND name = ANY($4)",
+        &[
+          &key,
+          &account_id,
+          &token,
+          &changes.removed,
+        ]
+      )
+      .await?;
+    }
+    let updated = self.fetch_token_attrs_tx(&tx, account_id, &key, &token).await?;
+    
+    tx.commit().await?;
+    Ok(updated)
+  }
+  
+  pub async fn fetch_token_attrs(&self, account_id: i64, key: String, token: String) -> Result<attrs::Attrs, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    self.fetch_token_attrs_tx(&*client, account_id, &key, &token).await
+  }
+  
+  pub async fn delete_token_attrs(&self, account_id: i64, key: String, token: String, if_match: Option<&str>) -> Result<(), error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_token_attrs(&tx, account_id, &key, &token).await?;
+    
+    // removing every attribute also removes those the schema requires
+    let existing = self.fetch_token_attrs_tx(&tx, account_id, &key, &token).await?;
+    existing.check_precondition(if_match)?;
+    if existing.attrs.len() > 0 {
+      self.check_token_attrs(&tx, account_id, &key, &collections::HashMap::new(), &collections::HashMap::new()).await?;
+    }
+    
+    tx.execute("
+      DELETE FROM mn_token_attr
+      WHERE key = $1 AND creator_id = $2 AND token = $3",
+      &[
+        &key,
+        &account_id,
+        &token,
+      ]
+    )
+    .await?;
+    
+    tx.commit().await?;
+    Ok(())
+  }
+  
+  pub async fn store_token_attr(&self, account_id: i64, key: String, token: String, name: &str, value: &serde_json::Value) -> Result<(), error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_token_attrs(&tx, account_id, &key, &token).await?;
+    
+    let attrs = attrs::Attrs::singleton(name.to_string(), value.clone()).attrs;
+    let existing = self.fetch_token_attrs_tx(&tx, account_id, &key, &token).await?;
+    let mut after = existing.attrs.clone();
+    after.extend(attrs.clone());
+    self.check_attr_limits(&tx, account_id, &attrs, &existing.attrs, &after).await?;
+    self.check_token_attrs(&tx, account_id, &key, &attrs, &existing.attrs).await?;
+    self.upsert_token_attrs_tx(&tx, account_id, &key, &token, &attrs).await?;
+    
+    tx.commit().await?;
+    Ok(())
+  }
+  
+  pub async fn fetch_token_attr(&self, account_id: i64, key: String, token: String, name: String) -> Result<serde_json::Value, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    let stream = client.query_raw("
+      SELECT kind, value FROM mn_token_attr
+      WHERE key = $1 AND creator_id = $2 AND token = $3 AND name = $4
+      ORDER BY key",
+      slice_iter(&[
+        &key,
+        &account_id,
+        &token,
+        &name,
+      ])
+    )
+    .await?;
+    pin_mut!(stream);
+    
+    match stream.try_next().await? {
+      Some(row) => {
+        let kind: String = row.try_get(0)?;
+        Ok(attrs::decode(&kind, row.try_get(1)?)?)
+      },
+      None => Err(error::Error::NotFoundError),
+    }
+  }
+  
+  pub async fn delete_token_attr(&self, account_id: i64, key: String, token: String, name: String) -> Result<(), error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_token_attrs(&tx, account_id, &key, &token).await?;
+    
+    // the attributes the token retains must still satisfy the schema
+    let mut retained = self.fetch_token_attrs_tx(&tx, account_id, &key, &token).await?.attrs;
+    if retained.remove(&name).is_some() {
+      self.check_token_attrs(&tx, account_id, &key, &collections::HashMap::new(), &retained).await?;
+    }
+    
+    tx.execute("
+      DELETE FROM mn_token_attr
+      WHERE key = $1 AND creator_id = $2 AND token = $3 AND name = $4",
+      &[
+        &key,
+        &account_id,
+        &token,
+        &name,
+      ]
+    )
+    .await?;
+    
+    tx.commit().await?;
+    Ok(())
+  }
+  
+  // Serialize writes to the attributes of a series. Attributes can only be
+  // stored for a series which exists and is not archived.
+  async fn lock_entry_attrs<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str) -> Result<(), error::Error> {
+    let row = client.query_opt("
+      SELECT 1 FROM mn_entry
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL
+      FOR UPDATE",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    match row {
+      Some(_) => Ok(()),
+      None => Err(error::Error::NotFoundError),
+    }
+  }
+  
+  // Fetch the attributes of a series along with the entity tag of the set.
+  async fn fetch_entry_attrs_tx<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str) -> Result<attrs::Attrs, error::Error> {
+    let rows = client.query("
+      SELECT name, kind, value FROM mn_entry_attr
+      WHERE key = $1 AND creator_id = $2",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    
+    let mut map: collections::HashMap<String, serde_json::Value> = collections::HashMap::new();
+    let mut stored: Vec<(String, String, String)> = Vec::new();
+    for row in rows {
+      let (name, kind, value): (String, String, String) = (row.try_get(0)?, row.try_get(1)?, row.try_get(2)?);
+      map.insert(name.to_owned(), attrs::decode(&kind, value.to_owned())?);
+      stored.push((name, kind, value));
+    }
+    
+    let etag = attrs::etag(&stored);
+    Ok(attrs::Attrs::new(map).with_etag(etag))
+  }
+  
+  async fn upsert_entry_attrs_tx<C: tokio_postgres::GenericClient + Sync>(&self, client: &C, account_id: i64, key: &str, attrs: &collections::HashMap<String, serde_json::Value>) -> Result<(), error::Error> {
+    for (name, value) in attrs {
+      let (kind, value) = attrs::encode(name, value)?;
+      client.execute("
+        INSERT INTO mn_entry_attr (key, creator_id, name, kind, value) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (key, creator_id, name) DO UPDATE SET kind = $4, value = $5, updated_at = now()",
+        &[
+          &key,
+          &account_id,
+          &name,
+          &kind.to_string(),
+          &value,
+        ]
+      )
+      .await?;
+    }
+    Ok(())
+  }
+  
+  // Store attributes for a series, returning the entity tag of the updated
+  // attribute set. Attributes which are not provided are retained.
+  pub async fn store_entry_attrs(&self, account_id: i64, key: String, attrs: &collections::HashMap<String, serde_json::Value>, if_match: Option<&str>) -> Result<String, error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_entry_attrs(&tx, account_id, &key).await?;
+    
+    let existing = self.fetch_entry_attrs_tx(&tx, account_id, &key).await?;
+    existing.check_precondition(if_match)?;
+    let mut after = existing.attrs.clone();
+    after.extend(attrs.clone());
+    self.check_attr_limits(&tx, account_id, attrs, &existing.attrs, &after).await?;
+    self.upsert_entry_attrs_tx(&tx, account_id, &key, attrs).await?;
+    let updated = self.fetch_entry_attrs_tx(&tx, account_id, &key).await?;
+    
+    tx.commit().await?;
+    Ok(updated.etag.unwrap_or_default())
+  }
+  
+  // Apply a JSON Merge Patch to the attributes of a series, returning the
+  // updated attribute set.
+  pub async fn patch_entry_attrs(&self, account_id: i64, key: String, patch: &serde_json::Value, if_match: Option<&str>) -> Result<attrs::Attrs, error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_entry_attrs(&tx, account_id, &key).await?;
+    
+    let existing = self.fetch_entry_attrs_tx(&tx, account_id, &key).await?;
+    existing.check_precondition(if_match)?;
+    
+    let changes = attrs::Changes::patch(&existing.attrs, patch)?;
+    self.check_attr_limits(&tx, account_id, &changes.changed, &existing.attrs, &changes.merged).await?;
+    self.upsert_entry_attrs_tx(&tx, account_id, &key, &changes.changed).await?;
+    if changes.removed.len() > 0 {
+      tx.execute("
+        DELETE FROM mn_entry_attr
+        WHERE key = $1 AND creator_id = $2 AND name = ANY($3)",
+        &[
+          &key,
+          &account_id,
+          &changes.removed,
+        ]
+      )
+      .await?;
+    }
+    let updated = self.fetch_entry_attrs_tx(&tx, account_id, &key).await?;
+    
+    tx.commit().await?;
+    Ok(updated)
+  }
+  
+  pub async fn fetch_entry_attrs(&self, account_id: i64, key: String) -> Result<attrs::Attrs, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    let row = client.query_opt("
+      SELECT 1 FROM mn_entry
+      WHERE key = $1 AND creator_id = $2 AND deleted_at IS NULL",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    if row.is_none() {
+      return Err(error::Error::NotFoundError);
+    }
+    
+    self.fetch_entry_attrs_tx(&*client, account_id, &key).await
+  }
+  
+  pub async fn delete_entry_attrs(&self, account_id: i64, key: String, if_match: Option<&str>) -> Result<(), error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_entry_attrs(&tx, account_id, &key).await?;
+    
+    if if_match.is_some() {
+      self.fetch_entry_attrs_tx(&tx, account_id, &key).await?.check_precondition(if_match)?;
+    }
+    
+    tx.execute("
+      DELETE FROM mn_entry_attr
+      WHERE key = $1 AND creator_id = $2",
+      &[
+        &key,
+        &account_id,
+      ]
+    )
+    .await?;
+    
+    tx.commit().await?;
+    Ok(())
+  }
+  
+  pub async fn store_entry_attr(&self, account_id: i64, key: String, name: &str, value: &serde_json::Value) -> Result<(), error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_entry_attrs(&tx, account_id, &key).await?;
+    
+    let attrs = attrs::Attrs::singleton(name.to_string(), value.clone()).attrs;
+    let existing = self.fetch_entry_attrs_tx(&tx, account_id, &key).await?;
+    let mut after = existing.attrs.clone();
+    after.extend(attrs.clone());
+    self.check_attr_limits(&tx, account_id, &attrs, &existing.attrs, &after).await?;
+    self.upsert_entry_attrs_tx(&tx, account_id, &key, &attrs).await?;
+    
+    tx.commit().await?;
+    Ok(())
+  }
+  
+  pub async fn fetch_entry_attr(&self, account_id: i64, key: String, name: String) -> Result<serde_json::Value, error::Error> {
+    let client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    
+    let row = client.query_opt("
+      SELECT a.kind, a.value FROM mn_entry_attr AS a
+      INNER JOIN mn_entry AS e ON e.key = a.key AND e.creator_id = a.creator_id
+      WHERE a.key = $1 AND a.creator_id = $2 AND a.name = $3 AND e.deleted_at IS NULL",
+      &[
+        &key,
+        &account_id,
+        &name,
+      ]
+    )
+    .await?;
+    
+    match row {
+      Some(row) => {
+        let kind: String = row.try_get(0)?;
+        Ok(attrs::decode(&kind, row.try_get(1)?)?)
+      },
+      None => Err(error::Error::NotFoundError),
+    }
+  }
+  
+  pub async fn delete_entry_attr(&self, account_id: i64, key: String, name: String) -> Result<(), error::Error> {
+    let mut client = self.pool.get().await?;
+    let key = self.resolve_key(&*client, account_id, &key).await?;
+    let tx = client.transaction().await?;
+    self.lock_entry_attrs(&tx, account_id, &key).await?;
+    
+    tx.execute("
+      DELETE FROM mn_entry_attr
+      WHERE key = $1 AND creator_id = $2 AND name = $3",
+      &[
+        &key,
+        &account_id,
+        &name,
+      ]
+    )
+    .await?;
+    
+    tx.commit().await?;
+    Ok(())
+  }
+  
+}
+
+fn semver_parts(v: &Option<semver::Semver>) -> (Option<i64>, Option<i64>, Option<i64>) {
+  match v {
+    Some(v) => (Some(v.major), Some(v.minor), Some(v.patch)),
+    None => (None, None, None),
+  }
+}
+
+fn calver_parts(v: &Option<calver::Calver>) -> (Option<String>, Option<i32>, Option<chrono::NaiveDate>, Option<i64>) {
+  match v {
+    Some(v) => (Some(v.period.to_string()), Some(v.utc_offset), v.start, Some(v.number)),
+    None => (None, None, None, None),
+  }
+}
+
+// Hash an API Key secret. Hashing is slow by design, so it is performed on
+// the blocking thread pool rather than on an async worker.
+async fn hash_secret(secret: String) -> Result<String, error::Error> {
+  tokio::task::spawn_blocking(move || apikey::hash_secret(&secret)).await?
+}
+
+async fn verify_secret(secret: String, stored: String) -> Result<bool, error::Error> {
+  Ok(tokio::task::spawn_blocking(move || apikey::verify_secret(&secret, &stored)).await?)
 }
 
 fn slice_iter<'a>(
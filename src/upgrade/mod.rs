@@ -58,6 +58,8 @@ impl<R: IntoRead, D: Driver<R>> Upgrader<R, D> {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::upgrade::driver::mock;
+  use crate::upgrade::io::BytesIntoRead;
   
   #[test]
   fn upgrade_success() {